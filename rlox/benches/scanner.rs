@@ -0,0 +1,46 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use rlox::byte_scanner::ByteScanner;
+use rlox::scanner;
+
+/**
+ * A big generated script, roughly what our code generators spit out
+ */
+fn generated_source() -> String {
+    let snippet = r#"
+// counter number {i}
+var counter_{i} = 0;
+fun step_{i}(amount, limit) {
+    if (counter_{i} + amount >= limit and !false) {
+        counter_{i} = counter_{i} - limit * 2.5;
+    }
+    print "step " + counter_{i};
+    return counter_{i} != nil;
+}
+"#;
+
+    (0..5000).map(|i| snippet.replace("{i}", &i.to_string())).collect()
+}
+
+/**
+ * Compares the two scanners as they are now. `scan_tokens` already has the
+ * static keyword match the ByteScanner work brought in, so this isn't a
+ * measure against the old scanner that rebuilt the keyword map for every
+ * identifier, just what scanning bytes and borrowing lexemes adds on top
+ */
+fn scanner_benchmark(c: &mut Criterion) {
+    let source = generated_source();
+
+    let mut group = c.benchmark_group("scan");
+    group.throughput(Throughput::Bytes(source.len() as u64));
+    group.bench_function("char_scanner", |b| {
+        b.iter(|| scanner::scan_tokens(black_box(&source)).unwrap().len())
+    });
+    group.bench_function("byte_scanner", |b| {
+        b.iter(|| ByteScanner::new(black_box(&source)).count())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, scanner_benchmark);
+criterion_main!(benches);
@@ -0,0 +1,356 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use crate::scanner::{self, ScanError, SourceIter, Span, SpannedToken, Token};
+
+/**
+ * Interned identifier, only meaningful alongside the `Interner` it came from
+ */
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+pub struct Symbol(u32);
+
+/**
+ * Hands out one `Symbol` per distinct name. Names are borrowed from the
+ * source unless NFC normalisation had to change them.
+ */
+#[derive(Default, Debug)]
+pub struct Interner<'a> {
+    symbols: HashMap<Cow<'a, str>, Symbol>,
+    names: Vec<Cow<'a, str>>,
+}
+
+impl<'a> Interner<'a> {
+    pub fn intern(&mut self, name: Cow<'a, str>) -> Symbol {
+        if let Some(symbol) = self.symbols.get(name.as_ref()) {
+            return *symbol;
+        }
+
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.clone());
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+/**
+ * Same tokens as `scanner::Token`, but literals borrow from the source
+ * instead of owning a `String`
+ */
+#[derive(PartialEq, Debug, Clone)]
+pub enum ByteToken<'a> {
+    Identifier(Symbol),
+    // only owned if it had escapes in it
+    String(Cow<'a, str>),
+    Number(f64),
+    // punctuation, keywords and EOF, nothing to borrow there. Interpolated
+    // strings end up here too, as the char scanner made them
+    Simple(Token),
+}
+
+impl ByteToken<'_> {
+    /**
+     * The equivalent owning token
+     */
+    pub fn to_token(&self, interner: &Interner<'_>) -> Token {
+        match self {
+            ByteToken::Identifier(symbol) => Token::IDENTIFIER(interner.resolve(*symbol).to_string()),
+            ByteToken::String(str) => Token::STRING(str.to_string()),
+            ByteToken::Number(x) => Token::NUMBER(*x),
+            ByteToken::Simple(token) => token.clone(),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpannedByteToken<'a> {
+    pub token: ByteToken<'a>,
+    pub span: Span,
+}
+
+/**
+ * Scanner for big inputs. Works over the raw bytes and handles the common
+ * ASCII cases itself without allocating; anything unusual (escapes,
+ * non-ASCII, fancy number syntax, block comments, errors) is handed to
+ * `scanner::scan_single_token` for that one token, so both scanners accept
+ * exactly the same language. Produces the same tokens and spans as
+ * `scanner::Scanner`.
+ */
+pub struct ByteScanner<'a> {
+    source: &'a str,
+    bytes: &'a [u8],
+    offset: usize,
+    line: usize,
+    column: usize,
+    interner: Interner<'a>,
+    done: bool,
+}
+
+fn is_identifier_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+impl<'a> ByteScanner<'a> {
+    pub fn new(source: &'a str) -> Self {
+        ByteScanner {
+            source,
+            bytes: source.as_bytes(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            interner: Interner::default(),
+            done: false,
+        }
+    }
+
+    pub fn interner(&self) -> &Interner<'a> {
+        &self.interner
+    }
+
+    pub fn into_interner(self) -> Interner<'a> {
+        self.interner
+    }
+
+    fn peek_byte(&self, ahead: usize) -> Option<u8> {
+        self.bytes.get(self.offset + ahead).copied()
+    }
+
+    fn current_position(&self) -> Span {
+        Span { offset: self.offset, line: self.line, column: self.column, len: 0 }
+    }
+
+    /**
+     * Moves forward `len` bytes, keeping line and column (in chars) right
+     */
+    fn advance(&mut self, len: usize) {
+        for &b in &self.bytes[self.offset..self.offset + len] {
+            if b == b'\n' {
+                self.line += 1;
+                self.column = 1;
+            }
+            // don't count UTF-8 continuation bytes as columns
+            else if b & 0xC0 != 0x80 {
+                self.column += 1;
+            }
+        }
+        self.offset += len;
+    }
+
+    /**
+     * Length of the run of bytes from `from` on that satisfy `pred`
+     */
+    fn run_length(&self, from: usize, pred: fn(u8) -> bool) -> usize {
+        self.bytes[self.offset + from..].iter().take_while(|b| pred(**b)).count()
+    }
+
+    fn finish(&mut self, token: ByteToken<'a>, len: usize) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        let span = Span { len, ..self.current_position() };
+        self.advance(len);
+        Some(Ok(SpannedByteToken { token, span }))
+    }
+
+    /**
+     * Scans one token with the char based scanner
+     */
+    fn slow_path(&mut self) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        let mut iter = SourceIter::starting_at(self.source, self.current_position());
+        let scanned = scanner::scan_single_token(&mut iter, false);
+
+        let end = iter.position();
+        self.offset = end.offset;
+        self.line = end.line;
+        self.column = end.column;
+
+        let SpannedToken { token, span } = match scanned? {
+            Ok(token) => token,
+            Err(error) => return Some(Err(error)),
+        };
+        let lexeme = &self.source[span.offset..span.offset + span.len];
+        let token = match token {
+            // borrow it if normalising didn't change anything
+            Token::IDENTIFIER(name) if name == lexeme => ByteToken::Identifier(self.interner.intern(Cow::Borrowed(lexeme))),
+            Token::IDENTIFIER(name) => ByteToken::Identifier(self.interner.intern(Cow::Owned(name))),
+            Token::STRING(str) => ByteToken::String(Cow::Owned(str)),
+            Token::NUMBER(x) => ByteToken::Number(x),
+            other => ByteToken::Simple(other),
+        };
+        Some(Ok(SpannedByteToken { token, span }))
+    }
+
+    fn string(&mut self) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        if self.peek_byte(1) == Some(b'"') && self.peek_byte(2) == Some(b'"') {
+            return self.slow_path();
+        }
+
+        let len = self.run_length(1, |b| b != b'"' && b != b'\\' && b != b'$');
+        match self.peek_byte(1 + len) {
+            Some(b'"') => {
+                let contents = &self.source[self.offset + 1..self.offset + 1 + len];
+                self.finish(ByteToken::String(Cow::Borrowed(contents)), len + 2)
+            }
+            // escapes, interpolation or unterminated
+            _ => self.slow_path(),
+        }
+    }
+
+    fn number(&mut self) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        let mut len = self.run_length(0, |b| b.is_ascii_digit());
+        if self.peek_byte(len) == Some(b'.') && self.peek_byte(len + 1).is_some_and(|b| b.is_ascii_digit()) {
+            len += 1 + self.run_length(len + 1, |b| b.is_ascii_digit());
+        }
+
+        // hex, binary, exponents, separators, 123abc and non-ASCII after it
+        if self.peek_byte(len).is_some_and(|b| is_identifier_byte(b) || !b.is_ascii()) {
+            return self.slow_path();
+        }
+
+        let value: f64 = self.source[self.offset..self.offset + len].parse().expect("Only digits and a .");
+        if value.is_infinite() {
+            return self.slow_path();
+        }
+        self.finish(ByteToken::Number(value), len)
+    }
+
+    fn identifier(&mut self) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        let len = self.run_length(0, is_identifier_byte);
+        let raw_string = len == 1 && self.peek_byte(0) == Some(b'r') && matches!(self.peek_byte(1), Some(b'"' | b'#'));
+        if raw_string || self.peek_byte(len).is_some_and(|b| !b.is_ascii()) {
+            return self.slow_path();
+        }
+
+        let name = &self.source[self.offset..self.offset + len];
+        let token = match scanner::keyword(name) {
+            Some(keyword) => ByteToken::Simple(keyword),
+            None => ByteToken::Identifier(self.interner.intern(Cow::Borrowed(name))),
+        };
+        self.finish(token, len)
+    }
+
+    fn one_or_two(&mut self, if_equal: Token, otherwise: Token) -> Option<Result<SpannedByteToken<'a>, ScanError>> {
+        if self.peek_byte(1) == Some(b'=') {
+            self.finish(ByteToken::Simple(if_equal), 2)
+        }
+        else {
+            self.finish(ByteToken::Simple(otherwise), 1)
+        }
+    }
+}
+
+impl<'a> Iterator for ByteScanner<'a> {
+    type Item = Result<SpannedByteToken<'a>, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(current) = self.peek_byte(0) else {
+                if self.done {
+                    return None;
+                }
+                self.done = true;
+                return Some(Ok(SpannedByteToken { token: ByteToken::Simple(Token::EOF), span: self.current_position() }));
+            };
+
+            let simple = match current {
+                b' ' | b'\t' | b'\r' | b'\n' => {
+                    self.advance(1);
+                    continue;
+                }
+                b'/' if self.peek_byte(1) == Some(b'/') => {
+                    let len = self.run_length(0, |b| b != b'\n');
+                    self.advance(len);
+                    continue;
+                }
+                b'(' => Token::LEFT_PAREN,
+                b')' => Token::RIGHT_PAREN,
+                b'{' => Token::LEFT_BRACE,
+                b'}' => Token::RIGHT_BRACE,
+                b'[' => Token::LEFT_BRACKET,
+                b']' => Token::RIGHT_BRACKET,
+                b',' => Token::COMMA,
+                b'.' => Token::DOT,
+                b'-' => Token::MINUS,
+                b'+' => Token::PLUS,
+                b';' => Token::SEMICOLON,
+                b'*' => Token::STAR,
+                b':' => Token::COLON,
+                b'?' => Token::QUESTION,
+                b'/' if self.peek_byte(1) != Some(b'*') => Token::SLASH,
+                b'!' => return self.one_or_two(Token::BANG_EQUAL, Token::BANG),
+                b'=' => return self.one_or_two(Token::EQUAL_EQUAL, Token::EQUAL),
+                b'<' => return self.one_or_two(Token::LESS_EQUAL, Token::LESS),
+                b'>' => return self.one_or_two(Token::GREATER_EQUAL, Token::GREATER),
+                b'"' => return self.string(),
+                b'0'..=b'9' => return self.number(),
+                b if b.is_ascii_alphabetic() || b == b'_' => return self.identifier(),
+                // block comments, non-ASCII and bad chars
+                _ => match self.slow_path() {
+                    Some(item) => return Some(item),
+                    // it was only a comment
+                    None => continue,
+                },
+            };
+            return self.finish(ByteToken::Simple(simple), 1);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use crate::byte_scanner::{ByteScanner, ByteToken};
+    use crate::scanner::{ScanError, Scanner, SpannedToken};
+
+    fn scan_owned(source: &str) -> Vec<Result<SpannedToken, ScanError>> {
+        let mut scanner = ByteScanner::new(source);
+        let mut result = Vec::new();
+        while let Some(item) = scanner.next() {
+            result.push(item.map(|t| SpannedToken { token: t.token.to_token(scanner.interner()), span: t.span }));
+        }
+        result
+    }
+
+    #[test]
+    fn matches_scanner_tests() {
+        let sources = [
+            "",
+            "var x = 1;\nprint x + 2.5 * (3 - 4) / 5;",
+            "!= ! == = <= < >= > // comment\n/ /* block /* nested */ */ .",
+            "fun f(a, b) { return a and b or !nil; } class C < D { init() { this.x = super.y; } }",
+            "\"plain\" \"esc\\n\\u{41}\" \"multi\nline\" \"bad\\q\" \"unterminated",
+            "0x1F 0b101 1e9 2.5E-3 1_000 1. 1.foo 123abc 1e999",
+            "größe _x x_1 cafe\u{301} caf\u{e9} // ünïcode comment\n@ # 😀 π",
+            "/* unterminated",
+            "\"é\" + é",
+            "\"x = ${x + 1}\" \"$ \\${\" \"${\"${y}\"}\" \"${",
+            "r\"raw\\n\" r#\"a\"b\"# rx r \"\"\"\n  triple\n  \"\"\" \"\" \"\"\"\"\"\"",
+            "outer: while (true) { break outer; continue; }",
+            "a ? b : c, d ?? :",
+            "var xs = [1, \"two\", [3]]; xs[0] = xs[-1][0];",
+        ];
+
+        for source in sources {
+            let expected: Vec<_> = Scanner::new(source).collect();
+            assert_eq!(scan_owned(source), expected, "source: {:?}", source);
+        }
+    }
+
+    #[test]
+    fn borrows_and_interns_tests() {
+        let source = "foo \"bar\" foo baz \"a\\tb\"";
+        let mut scanner = ByteScanner::new(source);
+        let tokens: Vec<ByteToken> = scanner.by_ref().map(|t| t.unwrap().token).collect();
+
+        let (ByteToken::Identifier(first), ByteToken::Identifier(second), ByteToken::Identifier(third)) =
+            (&tokens[0], &tokens[2], &tokens[3]) else { panic!("Expected identifiers") };
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+        assert_eq!(scanner.interner().resolve(*third), "baz");
+
+        assert!(matches!(&tokens[1], ByteToken::String(Cow::Borrowed("bar"))));
+        assert!(matches!(&tokens[4], ByteToken::String(Cow::Owned(str)) if str == "a\tb"));
+    }
+}
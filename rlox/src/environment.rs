@@ -0,0 +1,132 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::interpreter::ValueType;
+
+/**
+ * Variable bindings for one scope, lookups that miss fall through to the
+ * enclosing scope and eventually the globals. Scopes are shared since
+ * closures hang on to the one they were defined in.
+ */
+#[derive(Default, Debug)]
+pub struct Environment {
+    values: HashMap<String, ValueType>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment::default()
+    }
+
+    /**
+     * A fresh scope nested inside `enclosing`
+     */
+    pub fn with_enclosing(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment { values: HashMap::new(), enclosing: Some(enclosing) }
+    }
+
+    /**
+     * The outermost scope in the chain
+     */
+    pub fn globals(environment: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+        match &environment.borrow().enclosing {
+            Some(enclosing) => Environment::globals(enclosing),
+            None => Rc::clone(environment)
+        }
+    }
+
+    /**
+     * Redefining an existing variable is fine, same as in jlox
+     */
+    pub fn define(&mut self, name: String, value: ValueType) {
+        self.values.insert(name, value);
+    }
+
+    /**
+     * Returns false if there's no such variable to assign to
+     */
+    pub fn assign(&mut self, name: &str, value: ValueType) -> bool {
+        match self.values.get_mut(name) {
+            Some(slot) => {
+                *slot = value;
+                true
+            }
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+                None => false
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<ValueType> {
+        match self.values.get(name) {
+            Some(value) => Some(value.clone()),
+            None => self.enclosing.as_ref().and_then(|enclosing| enclosing.borrow().get(name))
+        }
+    }
+
+    /**
+     * Looks in exactly the scope `distance` hops out, like the resolver said
+     */
+    pub fn get_at(&self, distance: usize, name: &str) -> Option<ValueType> {
+        match distance {
+            0 => self.values.get(name).cloned(),
+            _ => self.enclosing.as_ref()?.borrow().get_at(distance - 1, name)
+        }
+    }
+
+    pub fn assign_at(&mut self, distance: usize, name: &str, value: ValueType) -> bool {
+        match distance {
+            0 => match self.values.get_mut(name) {
+                Some(slot) => {
+                    *slot = value;
+                    true
+                }
+                None => false
+            },
+            _ => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+                None => false
+            }
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::environment::Environment;
+    use crate::interpreter::ValueType;
+
+    #[test]
+    fn nested_scope_tests() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define(String::from("a"), ValueType::Number(1.0));
+        globals.borrow_mut().define(String::from("b"), ValueType::Number(2.0));
+
+        let inner = Rc::new(RefCell::new(Environment::with_enclosing(Rc::clone(&globals))));
+        inner.borrow_mut().define(String::from("a"), ValueType::Nil);
+        assert_eq!(inner.borrow().get("a"), Some(ValueType::Nil));
+        assert_eq!(inner.borrow().get("b"), Some(ValueType::Number(2.0)));
+
+        // assigning goes to wherever the variable lives
+        assert!(inner.borrow_mut().assign("b", ValueType::Boolean(true)));
+        assert!(!inner.borrow_mut().assign("c", ValueType::Nil));
+
+        assert_eq!(globals.borrow().get("a"), Some(ValueType::Number(1.0)));
+        assert_eq!(globals.borrow().get("b"), Some(ValueType::Boolean(true)));
+        assert!(Rc::ptr_eq(&Environment::globals(&inner), &globals));
+
+        // skips the inner a
+        assert_eq!(inner.borrow().get_at(1, "a"), Some(ValueType::Number(1.0)));
+        assert_eq!(inner.borrow().get_at(0, "b"), None);
+        assert!(inner.borrow_mut().assign_at(1, "a", ValueType::Number(3.0)));
+        assert_eq!(globals.borrow().get("a"), Some(ValueType::Number(3.0)));
+        assert_eq!(inner.borrow().get("a"), Some(ValueType::Nil));
+    }
+}
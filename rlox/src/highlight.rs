@@ -0,0 +1,189 @@
+use crate::scanner::{self, Scanner, SpannedToken, StringSegment, Token};
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Class {
+    Keyword,
+    String,
+    Number,
+    Identifier,
+    Operator,
+    Punctuation,
+    Comment,
+    // whitespace, nothing to colour
+    Plain,
+    // text the scanner couldn't make a token out of
+    Error,
+}
+
+impl Class {
+    fn css_class(self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("lox-keyword"),
+            Class::String => Some("lox-string"),
+            Class::Number => Some("lox-number"),
+            Class::Identifier => Some("lox-identifier"),
+            Class::Operator => Some("lox-operator"),
+            Class::Punctuation => Some("lox-punctuation"),
+            Class::Comment => Some("lox-comment"),
+            Class::Plain => None,
+            Class::Error => Some("lox-error"),
+        }
+    }
+
+    fn ansi_style(self) -> Option<&'static str> {
+        match self {
+            Class::Keyword => Some("\x1b[1;35m"),
+            Class::String => Some("\x1b[32m"),
+            Class::Number => Some("\x1b[36m"),
+            Class::Operator => Some("\x1b[33m"),
+            Class::Comment => Some("\x1b[90m"),
+            Class::Error => Some("\x1b[4;31m"),
+            Class::Identifier | Class::Punctuation | Class::Plain => None,
+        }
+    }
+}
+
+pub enum Format {
+    Ansi,
+    Html,
+}
+
+fn classify(token: &Token, lexeme: &str) -> Class {
+    match token {
+        // covers true, false and nil as well
+        _ if scanner::keyword(lexeme).is_some() => Class::Keyword,
+        Token::IDENTIFIER(_) => Class::Identifier,
+        Token::STRING(_) | Token::INTERPOLATED(_) => Class::String,
+        Token::NUMBER(_) => Class::Number,
+        Token::COMMENT(_) => Class::Comment,
+        Token::WHITESPACE(_) | Token::NEWLINE | Token::EOF => Class::Plain,
+        Token::LEFT_PAREN | Token::RIGHT_PAREN | Token::LEFT_BRACE | Token::RIGHT_BRACE |
+        Token::LEFT_BRACKET | Token::RIGHT_BRACKET |
+        Token::COMMA | Token::DOT | Token::SEMICOLON | Token::COLON => Class::Punctuation,
+        _ => Class::Operator,
+    }
+}
+
+/**
+ * Splits the source into (start, end, class) ranges that cover all of it
+ */
+fn classify_source(source: &str) -> Vec<(usize, usize, Class)> {
+    fn push_token(source: &str, token: &SpannedToken, ranges: &mut Vec<(usize, usize, Class)>) {
+        let start = token.span.offset;
+        let end = start + token.span.len;
+
+        // the code inside ${ } gets coloured like any other code, the rest
+        // of the string around it is string
+        if let Token::INTERPOLATED(segments) = &token.token {
+            let mut cursor = start;
+            for segment in segments {
+                if let StringSegment::Code(tokens) = segment {
+                    for inner in tokens.iter().filter(|inner| inner.token != Token::EOF) {
+                        if inner.span.offset > cursor {
+                            ranges.push((cursor, inner.span.offset, Class::String));
+                        }
+                        push_token(source, inner, ranges);
+                        cursor = inner.span.offset + inner.span.len;
+                    }
+                }
+            }
+            ranges.push((cursor, end, Class::String));
+            return;
+        }
+
+        ranges.push((start, end, classify(&token.token, &source[start..end])));
+    }
+
+    let mut ranges = Vec::new();
+    let mut cursor = 0;
+    // errors don't always span everything they swallowed, so anything
+    // between two good tokens counts as the error
+    for token in Scanner::lossless(source).flatten() {
+        if token.span.offset > cursor {
+            ranges.push((cursor, token.span.offset, Class::Error));
+        }
+        push_token(source, &token, &mut ranges);
+        cursor = token.span.offset + token.span.len;
+    }
+    ranges.retain(|(start, end, _)| start < end);
+    ranges
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+/**
+ * Colours Lox source the way the scanner sees it, either with ANSI escapes
+ * for a terminal or as a <pre> block with lox-* CSS classes on each span
+ */
+pub fn highlight(source: &str, format: Format) -> String {
+    let ranges = classify_source(source);
+
+    match format {
+        Format::Ansi => ranges.iter().map(|(start, end, class)| {
+            let text = &source[*start..*end];
+            match class.ansi_style() {
+                Some(style) => format!("{}{}\x1b[0m", style, text),
+                None => text.to_string(),
+            }
+        }).collect(),
+        Format::Html => {
+            let code: String = ranges.iter().map(|(start, end, class)| {
+                let text = escape_html(&source[*start..*end]);
+                match class.css_class() {
+                    Some(css_class) => format!("<span class=\"{}\">{}</span>", css_class, text),
+                    None => text,
+                }
+            }).collect();
+            format!("<pre class=\"lox\"><code>{}</code></pre>\n", code)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::highlight::{classify_source, highlight, Class, Format};
+
+    fn classes(source: &str) -> Vec<(&str, Class)> {
+        classify_source(source).into_iter()
+            .map(|(start, end, class)| (&source[start..end], class))
+            .collect()
+    }
+
+    #[test]
+    fn classify_tests() {
+        assert_eq!(classes("var x = nil; // hi\n"), vec![
+            ("var", Class::Keyword), (" ", Class::Plain), ("x", Class::Identifier), (" ", Class::Plain),
+            ("=", Class::Operator), (" ", Class::Plain), ("nil", Class::Keyword), (";", Class::Punctuation),
+            (" ", Class::Plain), ("// hi", Class::Comment), ("\n", Class::Plain),
+        ]);
+
+        assert_eq!(classes("\"a${b + 1}c\""), vec![
+            ("\"a${", Class::String), ("b", Class::Identifier), (" ", Class::String), ("+", Class::Operator),
+            (" ", Class::String), ("1", Class::Number), ("}c\"", Class::String),
+        ]);
+
+        // bad input still comes out, marked as an error
+        assert_eq!(classes("1 @ \"a\\q\" 2"), vec![
+            ("1", Class::Number), (" ", Class::Plain), ("@", Class::Error), (" ", Class::Plain),
+            ("\"a\\q\"", Class::Error), (" ", Class::Plain), ("2", Class::Number),
+        ]);
+        assert_eq!(classes("x /* never closed"), vec![
+            ("x", Class::Identifier), (" ", Class::Plain), ("/* never closed", Class::Error),
+        ]);
+    }
+
+    #[test]
+    fn highlight_tests() {
+        assert_eq!(highlight("print a < \"<b>\";", Format::Html),
+            "<pre class=\"lox\"><code><span class=\"lox-keyword\">print</span> \
+            <span class=\"lox-identifier\">a</span> <span class=\"lox-operator\">&lt;</span> \
+            <span class=\"lox-string\">&quot;&lt;b&gt;&quot;</span><span class=\"lox-punctuation\">;</span>\
+            </code></pre>\n");
+
+        assert_eq!(highlight("if (x) 1;", Format::Ansi),
+            "\x1b[1;35mif\x1b[0m (x) \x1b[36m1\x1b[0m;");
+    }
+}
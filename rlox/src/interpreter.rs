@@ -1,164 +1,1166 @@
-use crate::parser::Expr;
-use crate::parser::Stmt;
-pub use crate::scanner::Token;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::environment::Environment;
+use crate::parser::{variable_name, Expr, Function, Stmt};
+pub use crate::scanner::{SpannedToken, Token};
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum ValueType {
     Number(f64),
     Boolean(bool),
     String(String),
+    Callable(Rc<dyn Callable>),
+    Class(Rc<LoxClass>),
+    Instance(Rc<RefCell<LoxInstance>>),
+    // shared, so changes through one variable show up in the others
+    List(Rc<RefCell<Vec<ValueType>>>),
     Nil,
 }
 
-pub fn stmt_eval(statement: &Stmt) {
+/**
+ * Anything that can go on the left of a call, Lox functions and the native
+ * ones written in Rust
+ */
+pub trait Callable: fmt::Debug {
+    fn arity(&self) -> usize;
+    /**
+     * `paren` is the call's closing paren, for errors
+     */
+    fn call(self: Rc<Self>, paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError>;
+    fn to_string(&self) -> String;
+}
+
+// functions are only equal to themselves
+impl PartialEq for dyn Callable {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(self, other)
+    }
+}
+
+pub struct LoxFunction {
+    declaration: Rc<Function>,
+    // the scope the function was declared in
+    closure: Rc<RefCell<Environment>>,
+    // init always hands back this, whatever it returns
+    is_initializer: bool,
+}
+
+impl LoxFunction {
+    /**
+     * The method with `this` set to `instance`, in a scope just outside its body
+     */
+    fn bind(&self, instance: Rc<RefCell<LoxInstance>>) -> LoxFunction {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        environment.define(String::from("this"), ValueType::Instance(instance));
+        LoxFunction {
+            declaration: Rc::clone(&self.declaration),
+            closure: Rc::new(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+
+    fn this(&self) -> ValueType {
+        self.closure.borrow().get_at(0, "this").expect("Initializer should be bound")
+    }
+}
+
+// not derived, the closure usually holds the function itself
+impl fmt::Debug for LoxFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxFunction({})", variable_name(&self.declaration.name))
+    }
+}
+
+impl Callable for LoxFunction {
+    fn arity(&self) -> usize {
+        self.declaration.params.len()
+    }
+
+    fn call(self: Rc<Self>, _paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+        let mut environment = Environment::with_enclosing(Rc::clone(&self.closure));
+        for (param, argument) in self.declaration.params.iter().zip(arguments) {
+            environment.define(variable_name(param).to_string(), argument);
+        }
+
+        match execute_block(&self.declaration.body, Rc::new(RefCell::new(environment))) {
+            Ok(()) | Err(Unwind::Return(..)) if self.is_initializer => Ok(self.this()),
+            Ok(()) => Ok(ValueType::Nil),
+            Err(Unwind::Return(_, value)) => Ok(value),
+            Err(other) => Err(other.into_error()),
+        }
+    }
+
+    fn to_string(&self) -> String {
+        format!("<fn {}>", variable_name(&self.declaration.name))
+    }
+}
+
+pub struct NativeFunction {
+    name: &'static str,
+    arity: usize,
+    function: fn(&SpannedToken, Vec<ValueType>) -> Result<ValueType, RuntimeError>,
+}
+
+impl fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+impl Callable for NativeFunction {
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn call(self: Rc<Self>, paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+        (self.function)(paren, arguments)
+    }
+
+    fn to_string(&self) -> String {
+        String::from("<native fn>")
+    }
+}
+
+pub struct LoxClass {
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<String, Rc<LoxFunction>>,
+}
+
+// classes and instances are only equal to themselves too
+impl PartialEq for LoxClass {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl fmt::Debug for LoxClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxClass({})", self.name)
+    }
+}
+
+impl LoxClass {
+    /**
+     * Looks up the superclass chain if this class doesn't have it
+     */
+    fn find_method(&self, name: &str) -> Option<Rc<LoxFunction>> {
+        match self.methods.get(name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self.superclass.as_ref()?.find_method(name)
+        }
+    }
+}
+
+/**
+ * Calling a class makes an instance and runs init on it if there is one
+ */
+impl Callable for LoxClass {
+    fn arity(&self) -> usize {
+        self.find_method("init").map_or(0, |init| init.arity())
+    }
+
+    fn call(self: Rc<Self>, paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+        let instance = Rc::new(RefCell::new(LoxInstance { class: Rc::clone(&self), fields: HashMap::new() }));
+        if let Some(init) = self.find_method("init") {
+            Rc::new(init.bind(Rc::clone(&instance))).call(paren, arguments)?;
+        }
+        Ok(ValueType::Instance(instance))
+    }
+
+    fn to_string(&self) -> String {
+        self.name.clone()
+    }
+}
+
+pub struct LoxInstance {
+    class: Rc<LoxClass>,
+    fields: HashMap<String, ValueType>,
+}
+
+impl PartialEq for LoxInstance {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+// fields can point back at the instance, so no derive
+impl fmt::Debug for LoxInstance {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "LoxInstance({})", self.class.name)
+    }
+}
+
+/**
+ * Fields shadow methods, methods come back bound to the instance
+ */
+fn get_property(instance: &Rc<RefCell<LoxInstance>>, name: &SpannedToken) -> Result<ValueType, RuntimeError> {
+    if let Some(value) = instance.borrow().fields.get(variable_name(name)) {
+        return Ok(value.clone());
+    }
+
+    let method = instance.borrow().class.find_method(variable_name(name));
+    match method {
+        Some(method) => Ok(ValueType::Callable(Rc::new(method.bind(Rc::clone(instance))))),
+        None => runtime_error(name, format!("Undefined property '{}'.", variable_name(name)))
+    }
+}
+
+fn native_clock(_paren: &SpannedToken, _arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    Ok(ValueType::Number(now.as_secs_f64()))
+}
+
+/**
+ * What kind of value it is, for error messages
+ */
+fn type_description(value: &ValueType) -> &'static str {
+    match value {
+        ValueType::Number(_) => "a number",
+        ValueType::Boolean(_) => "a boolean",
+        ValueType::String(_) => "a string",
+        ValueType::Callable(_) => "a function",
+        ValueType::Class(_) => "a class",
+        ValueType::Instance(_) => "an instance",
+        ValueType::List(_) => "a list",
+        ValueType::Nil => "nil",
+    }
+}
+
+/**
+ * Turns `index` into a position in a list of length `len`. `past_end` lets
+ * it be len as well, for inserting at the end or slicing up to it.
+ */
+fn list_index(token: &SpannedToken, index: &ValueType, len: usize, past_end: bool) -> Result<usize, RuntimeError> {
+    let limit = if past_end { len + 1 } else { len };
+    match *index {
+        ValueType::Number(x) if x.fract() != 0.0 =>
+            runtime_error(token, format!("List index must be a whole number, got {}.", x)),
+        ValueType::Number(x) if x < 0.0 =>
+            runtime_error(token, format!("List index can't be negative, got {}.", x)),
+        ValueType::Number(x) if x as usize >= limit =>
+            runtime_error(token, format!("List index {} is out of range for a list of length {}.", x, len)),
+        ValueType::Number(x) => Ok(x as usize),
+        ref other => runtime_error(token, format!("List index must be a number, got {}.", type_description(other)))
+    }
+}
+
+fn list_argument(paren: &SpannedToken, function: &str, value: &ValueType) -> Result<Rc<RefCell<Vec<ValueType>>>, RuntimeError> {
+    match value {
+        ValueType::List(list) => Ok(Rc::clone(list)),
+        other => runtime_error(paren, format!("{}() expects a list, got {}.", function, type_description(other)))
+    }
+}
+
+fn native_len(paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    match &arguments[0] {
+        ValueType::List(list) => Ok(ValueType::Number(list.borrow().len() as f64)),
+        ValueType::String(str) => Ok(ValueType::Number(str.chars().count() as f64)),
+        other => runtime_error(paren, format!("len() expects a list or string, got {}.", type_description(other)))
+    }
+}
+
+fn native_push(paren: &SpannedToken, mut arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "push", &arguments[0])?;
+    list.borrow_mut().push(arguments.remove(1));
+    Ok(ValueType::Nil)
+}
+
+fn native_pop(paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "pop", &arguments[0])?;
+    let popped = list.borrow_mut().pop();
+    match popped {
+        Some(value) => Ok(value),
+        None => runtime_error(paren, String::from("Can't pop from an empty list."))
+    }
+}
+
+fn native_insert(paren: &SpannedToken, mut arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "insert", &arguments[0])?;
+    // inserting at the end is fine
+    let index = list_index(paren, &arguments[1], list.borrow().len(), true)?;
+    list.borrow_mut().insert(index, arguments.remove(2));
+    Ok(ValueType::Nil)
+}
+
+fn native_remove(paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "remove", &arguments[0])?;
+    let index = list_index(paren, &arguments[1], list.borrow().len(), false)?;
+    let removed = list.borrow_mut().remove(index);
+    Ok(removed)
+}
+
+/**
+ * slice(xs, start, end) is a new list of xs[start] up to but not including xs[end]
+ */
+fn native_slice(paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "slice", &arguments[0])?;
+    let len = list.borrow().len();
+    let start = list_index(paren, &arguments[1], len, true)?;
+    let end = list_index(paren, &arguments[2], len, true)?;
+    if start > end {
+        return runtime_error(paren, format!("Slice start {} is after its end {}.", start, end));
+    }
+
+    let slice = list.borrow()[start..end].to_vec();
+    Ok(ValueType::List(Rc::new(RefCell::new(slice))))
+}
+
+fn native_contains(paren: &SpannedToken, arguments: Vec<ValueType>) -> Result<ValueType, RuntimeError> {
+    let list = list_argument(paren, "contains", &arguments[0])?;
+    let found = list.borrow().iter().any(|element| is_equal(element, &arguments[1]));
+    Ok(ValueType::Boolean(found))
+}
+
+/**
+ * A global scope with the native functions already defined
+ */
+pub fn global_environment() -> Rc<RefCell<Environment>> {
+    let natives = [
+        NativeFunction { name: "clock", arity: 0, function: native_clock },
+        NativeFunction { name: "len", arity: 1, function: native_len },
+        NativeFunction { name: "push", arity: 2, function: native_push },
+        NativeFunction { name: "pop", arity: 1, function: native_pop },
+        NativeFunction { name: "insert", arity: 3, function: native_insert },
+        NativeFunction { name: "remove", arity: 2, function: native_remove },
+        NativeFunction { name: "slice", arity: 3, function: native_slice },
+        NativeFunction { name: "contains", arity: 2, function: native_contains },
+    ];
+
+    let mut globals = Environment::new();
+    for native in natives {
+        globals.define(native.name.to_string(), ValueType::Callable(Rc::new(native)));
+    }
+    Rc::new(RefCell::new(globals))
+}
+
+/**
+ * Error while running, `token` is whatever the program was doing at the time
+ */
+#[derive(PartialEq, Debug, Clone)]
+pub struct RuntimeError {
+    pub message: String,
+    pub token: SpannedToken,
+}
+
+impl fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] Runtime error: {}", self.token.span, self.message)
+    }
+}
+
+fn runtime_error<T>(token: &SpannedToken, message: String) -> Result<T, RuntimeError> {
+    Err(RuntimeError { message, token: token.clone() })
+}
+
+/**
+ * Why a statement stopped early, either something broke or a return is
+ * heading up to its call
+ */
+enum Unwind {
+    Error(RuntimeError),
+    // the return keyword and the value
+    Return(SpannedToken, ValueType),
+    // keyword and label name, heading for the loop they belong to
+    Break(SpannedToken, Option<String>),
+    Continue(SpannedToken, Option<String>),
+}
+
+impl Unwind {
+    /**
+     * For when it unwound somewhere with nothing to catch it, which the
+     * resolver should have ruled out already
+     */
+    fn into_error(self) -> RuntimeError {
+        let (token, message) = match self {
+            Unwind::Error(error) => return error,
+            Unwind::Return(keyword, _) => (keyword, "Can't return from top-level code."),
+            Unwind::Break(keyword, _) => (keyword, "Can't use 'break' outside of a loop."),
+            Unwind::Continue(keyword, _) => (keyword, "Can't use 'continue' outside of a loop."),
+        };
+        RuntimeError { message: message.to_string(), token }
+    }
+}
+
+/**
+ * No label means the innermost loop
+ */
+fn targets_loop(target: &Option<String>, label: &Option<SpannedToken>) -> bool {
+    match (target, label) {
+        (None, _) => true,
+        (Some(target), Some(label)) => target == variable_name(label),
+        (Some(_), None) => false,
+    }
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+pub fn stmt_eval(statement: &Stmt, environment: &Rc<RefCell<Environment>>) -> Result<(), RuntimeError> {
+    execute(statement, environment).map_err(Unwind::into_error)
+}
+
+/**
+ * Runs the statements in `environment`, which is dropped afterwards however
+ * they finished
+ */
+fn execute_block(statements: &[Stmt], environment: Rc<RefCell<Environment>>) -> Result<(), Unwind> {
+    statements.iter().try_for_each(|statement| execute(statement, &environment))
+}
+
+fn execute(statement: &Stmt, environment: &Rc<RefCell<Environment>>) -> Result<(), Unwind> {
     match statement {
         Stmt::Expression(expr) => {
-            let _ = evaluate(expr);
+            let _ = evaluate(expr, environment)?;
             // do nothing???
         },
         Stmt::Print(expr) => {
-            println!("{:?}", stringify_valuetype(&evaluate(expr)));
+            println!("{}", stringify_valuetype(&evaluate(expr, environment)?));
+        },
+        Stmt::Var(name, initializer) => {
+            let value = match initializer {
+                Some(expr) => evaluate(expr, environment)?,
+                None => ValueType::Nil
+            };
+            environment.borrow_mut().define(variable_name(name).to_string(), value);
+        }
+        Stmt::Block(statements) => {
+            let inner = Environment::with_enclosing(Rc::clone(environment));
+            execute_block(statements, Rc::new(RefCell::new(inner)))?;
+        }
+        Stmt::If(condition, then_branch, else_branch) => {
+            if is_truthy(&evaluate(condition, environment)?) {
+                execute(then_branch, environment)?;
+            }
+            else if let Some(else_branch) = else_branch {
+                execute(else_branch, environment)?;
+            }
+        }
+        Stmt::While(label, condition, body, increment) => {
+            while is_truthy(&evaluate(condition, environment)?) {
+                match execute(body, environment) {
+                    Ok(()) => {}
+                    Err(Unwind::Break(_, target)) if targets_loop(&target, label) => break,
+                    Err(Unwind::Continue(_, target)) if targets_loop(&target, label) => {}
+                    Err(other) => return Err(other),
+                }
+                if let Some(increment) = increment {
+                    evaluate(increment, environment)?;
+                }
+            }
+        }
+        Stmt::Break(keyword, label) => {
+            let target = label.as_ref().map(|label| variable_name(label).to_string());
+            return Err(Unwind::Break(keyword.clone(), target));
+        }
+        Stmt::Continue(keyword, label) => {
+            let target = label.as_ref().map(|label| variable_name(label).to_string());
+            return Err(Unwind::Continue(keyword.clone(), target));
+        }
+        Stmt::Function(declaration) => {
+            let function = LoxFunction { declaration: Rc::clone(declaration), closure: Rc::clone(environment), is_initializer: false };
+            environment.borrow_mut().define(variable_name(&declaration.name).to_string(),
+                                            ValueType::Callable(Rc::new(function)));
+        }
+        Stmt::Class(name, superclass, declarations) => {
+            let superclass = match superclass {
+                Some(expr) => match evaluate(expr, environment)? {
+                    ValueType::Class(class) => Some(class),
+                    _ => {
+                        let Expr::Variable(superclass_name, _) = expr else {
+                            panic!("Expected superclass to be a variable, got {:?}", expr)
+                        };
+                        return Err(Unwind::Error(RuntimeError {
+                            message: String::from("Superclass must be a class."),
+                            token: superclass_name.clone(),
+                        }));
+                    }
+                },
+                None => None
+            };
+
+            // methods of a subclass close over an extra scope holding super
+            let closure = match &superclass {
+                Some(superclass) => {
+                    let mut environment = Environment::with_enclosing(Rc::clone(environment));
+                    environment.define(String::from("super"), ValueType::Class(Rc::clone(superclass)));
+                    Rc::new(RefCell::new(environment))
+                }
+                None => Rc::clone(environment)
+            };
+
+            let methods = declarations.iter().map(|declaration| {
+                let method = LoxFunction {
+                    declaration: Rc::clone(declaration),
+                    closure: Rc::clone(&closure),
+                    is_initializer: variable_name(&declaration.name) == "init",
+                };
+                (variable_name(&declaration.name).to_string(), Rc::new(method))
+            }).collect();
+
+            let class = LoxClass { name: variable_name(name).to_string(), superclass, methods };
+            environment.borrow_mut().define(variable_name(name).to_string(), ValueType::Class(Rc::new(class)));
+        }
+        Stmt::Return(keyword, value) => {
+            let value = match value {
+                Some(expr) => evaluate(expr, environment)?,
+                None => ValueType::Nil
+            };
+            return Err(Unwind::Return(keyword.clone(), value));
         }
     }
+    Ok(())
 }
 
-fn evaluate(expression_tree: &Expr) -> ValueType{
+fn evaluate(expression_tree: &Expr, environment: &Rc<RefCell<Environment>>) -> Result<ValueType, RuntimeError> {
     match expression_tree {
-        Expr::Binary(left, op, right) => binary_funct(op, left, right),
-        Expr::Unary(op, expr) => unary_funct(op, expr),
-        Expr::Grouping(expr) => evaluate(expr),
+        Expr::Binary(left, op, right) => binary_funct(op, left, right, environment),
+        Expr::Logical(left, op, right) => {
+            let left = evaluate(left, environment)?;
+            // hand back whichever operand decided it, not a bool
+            match (&op.token, is_truthy(&left)) {
+                (Token::OR, true) | (Token::AND, false) => Ok(left),
+                _ => evaluate(right, environment)
+            }
+        },
+        Expr::Conditional(condition, then_branch, else_branch) => {
+            // only the branch that's taken runs
+            match is_truthy(&evaluate(condition, environment)?) {
+                true => evaluate(then_branch, environment),
+                false => evaluate(else_branch, environment)
+            }
+        },
+        Expr::Comma(left, right) => {
+            evaluate(left, environment)?;
+            evaluate(right, environment)
+        },
+        Expr::Unary(op, expr) => unary_funct(op, expr, environment),
+        Expr::Grouping(expr) => evaluate(expr, environment),
+        Expr::Interpolation(parts) => {
+            let mut result = String::new();
+            for part in parts {
+                result += &stringify_valuetype(&evaluate(part, environment)?);
+            }
+            Ok(ValueType::String(result))
+        },
+        Expr::Variable(name, depth) => {
+            let value = match depth.get() {
+                Some(distance) => environment.borrow().get_at(distance, variable_name(name)),
+                None => Environment::globals(environment).borrow().get(variable_name(name))
+            };
+            match value {
+                Some(value) => Ok(value),
+                None => runtime_error(name, format!("Undefined variable '{}'.", variable_name(name)))
+            }
+        },
+        Expr::Assign(name, value, depth) => {
+            let value = evaluate(value, environment)?;
+            let assigned = match depth.get() {
+                Some(distance) => environment.borrow_mut().assign_at(distance, variable_name(name), value.clone()),
+                None => Environment::globals(environment).borrow_mut().assign(variable_name(name), value.clone())
+            };
+            if !assigned {
+                return runtime_error(name, format!("Undefined variable '{}'.", variable_name(name)));
+            }
+            // assignment is an expression, a = b = 1 sets both
+            Ok(value)
+        },
+        Expr::Call(callee, paren, arguments) => {
+            let callee = evaluate(callee, environment)?;
+            let arguments = arguments.iter()
+                .map(|argument| evaluate(argument, environment))
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let function: Rc<dyn Callable> = match callee {
+                ValueType::Callable(function) => function,
+                ValueType::Class(class) => class,
+                _ => return runtime_error(paren, String::from("Can only call functions and classes."))
+            };
+            if arguments.len() != function.arity() {
+                return runtime_error(paren, format!("Expected {} arguments but got {}.", function.arity(), arguments.len()));
+            }
+            function.call(paren, arguments)
+        },
+
+        Expr::Get(object, name) => match evaluate(object, environment)? {
+            ValueType::Instance(instance) => get_property(&instance, name),
+            _ => runtime_error(name, String::from("Only instances have properties."))
+        },
+        Expr::Set(object, name, value) => {
+            let ValueType::Instance(instance) = evaluate(object, environment)? else {
+                return runtime_error(name, String::from("Only instances have fields."));
+            };
+            let value = evaluate(value, environment)?;
+            instance.borrow_mut().fields.insert(variable_name(name).to_string(), value.clone());
+            Ok(value)
+        },
+        Expr::Super(keyword, method, depth) => {
+            // super is one scope further out than this, see Stmt::Class
+            let distance = depth.get().expect("Resolver should have found super");
+            let superclass = environment.borrow().get_at(distance, "super");
+            let instance = environment.borrow().get_at(distance - 1, "this");
+            let (Some(ValueType::Class(superclass)), Some(ValueType::Instance(instance))) = (superclass, instance) else {
+                return runtime_error(keyword, String::from("Can't use 'super' outside of a class."));
+            };
+
+            match superclass.find_method(variable_name(method)) {
+                Some(found) => Ok(ValueType::Callable(Rc::new(found.bind(instance)))),
+                None => runtime_error(method, format!("Undefined property '{}'.", variable_name(method)))
+            }
+        },
+        Expr::List(elements) => {
+            let elements = elements.iter()
+                .map(|element| evaluate(element, environment))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ValueType::List(Rc::new(RefCell::new(elements))))
+        },
+        Expr::Index(list, bracket, index) => {
+            let ValueType::List(list) = evaluate(list, environment)? else {
+                return runtime_error(bracket, String::from("Only lists can be indexed."));
+            };
+            let index = evaluate(index, environment)?;
+
+            let list = list.borrow();
+            let index = list_index(bracket, &index, list.len(), false)?;
+            Ok(list[index].clone())
+        },
+        Expr::SetIndex(list, bracket, index, value) => {
+            let ValueType::List(list) = evaluate(list, environment)? else {
+                return runtime_error(bracket, String::from("Only lists can be indexed."));
+            };
+            let index = evaluate(index, environment)?;
+            let value = evaluate(value, environment)?;
+
+            let mut list = list.borrow_mut();
+            let index = list_index(bracket, &index, list.len(), false)?;
+            list[index] = value.clone();
+            Ok(value)
+        },
+        Expr::This(keyword, depth) => {
+            let distance = depth.get().expect("Resolver should have found this");
+            match environment.borrow().get_at(distance, "this") {
+                Some(value) => Ok(value),
+                None => runtime_error(keyword, String::from("Can't use 'this' outside of a class."))
+            }
+        },
 
         // //terminals
-        Expr::String(str) => ValueType::String(str.clone()),
-        Expr::Number(x) => ValueType::Number(*x),
-        Expr::Boolean(bool) => ValueType::Boolean(*bool),
-        Expr::Nil => ValueType::Nil
+        Expr::String(str) => Ok(ValueType::String(str.clone())),
+        Expr::Number(x) => Ok(ValueType::Number(*x)),
+        Expr::Boolean(bool) => Ok(ValueType::Boolean(*bool)),
+        Expr::Nil => Ok(ValueType::Nil)
     }
 }
 
 pub fn stringify_valuetype(val: &ValueType) -> String{
+    stringify_nested(val, &mut HashSet::new())
+}
+
+/**
+ * `printing` holds the lists we're already inside of, so a list that
+ * contains itself comes out as [...] instead of recursing forever
+ */
+fn stringify_nested(val: &ValueType, printing: &mut HashSet<*const RefCell<Vec<ValueType>>>) -> String {
     match val {
         ValueType::Boolean(b) => b.to_string(),
         ValueType::Nil => String::from("Nil"),
         ValueType::Number(x) => x.to_string(),
-        ValueType::String(s) => s.clone()
+        ValueType::String(s) => s.clone(),
+        ValueType::Callable(function) => function.to_string(),
+        ValueType::Class(class) => class.to_string(),
+        ValueType::Instance(instance) => format!("{} instance", instance.borrow().class.name),
+        ValueType::List(list) => {
+            if !printing.insert(Rc::as_ptr(list)) {
+                return String::from("[...]");
+            }
+            let elements: Vec<String> = list.borrow().iter().map(|element| match element {
+                // quoted so ["a, b"] and ["a", "b"] look different
+                ValueType::String(s) => format!("{:?}", s),
+                other => stringify_nested(other, printing)
+            }).collect();
+            printing.remove(&Rc::as_ptr(list));
+            format!("[{}]", elements.join(", "))
+        }
     }
 }
 
-fn binary_funct(operation: &Token, left_expr: &Expr, right_expr: &Expr) -> ValueType {
-    match operation {
+fn binary_funct(operation: &SpannedToken, left_expr: &Expr, right_expr: &Expr, environment: &Rc<RefCell<Environment>>) -> Result<ValueType, RuntimeError> {
+    let left_res = evaluate(left_expr, environment)?;
+    let right_res = evaluate(right_expr, environment)?;
+
+    match operation.token {
         Token::STAR => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-    
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Number(x*y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to * operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Number(x*y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to * operator", a, b))
             }
         },
         Token::SLASH => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-    
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Number(x/y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to / operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Number(x/y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to / operator", a, b))
             }
         },
         Token::MINUS => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-    
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Number(x-y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to - operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Number(x-y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to - operator", a, b))
             }
         },
         Token::PLUS => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-    
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Number(x+y),
-                (ValueType::String(x), ValueType::String(y)) => ValueType::String(x+&y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to + operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Number(x+y)),
+                (ValueType::String(x), ValueType::String(y)) => Ok(ValueType::String(x+&y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to + operator", a, b))
             }
         },
 
         Token::GREATER => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Boolean(x > y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to > operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Boolean(x > y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to > operator", a, b))
             }
         },
         Token::GREATER_EQUAL => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Boolean(x >= y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to >= operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Boolean(x >= y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to >= operator", a, b))
             }
         },
         Token::LESS => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Boolean(x < y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to < operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Boolean(x < y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to < operator", a, b))
             }
         },
         Token::LESS_EQUAL => {
-            let left_res = evaluate(left_expr);
-            let right_res = evaluate(right_expr);
-
             match (left_res, right_res){
-                (ValueType::Number(x), ValueType::Number(y)) => ValueType::Boolean(x <= y),
-                (a, b) => panic!("Bad arguments {:?} and {:?} to <= operator", a, b)
+                (ValueType::Number(x), ValueType::Number(y)) => Ok(ValueType::Boolean(x <= y)),
+                (a, b) => runtime_error(operation, format!("Bad arguments {:?} and {:?} to <= operator", a, b))
             }
         },
         Token::BANG_EQUAL =>
-            ValueType::Boolean(!is_equal(&evaluate(left_expr), &evaluate(right_expr))),
+            Ok(ValueType::Boolean(!is_equal(&left_res, &right_res))),
         Token::EQUAL_EQUAL =>
-            ValueType::Boolean(is_equal(&evaluate(left_expr), &evaluate(right_expr))),
-    
-        other => panic!("Bad binary operator: {:?}", other)
+            Ok(ValueType::Boolean(is_equal(&left_res, &right_res))),
+
+        ref other => runtime_error(operation, format!("Bad binary operator: {:?}", other))
     }
 }
 
+/**
+ * nil and false are falsey, everything else is truthy
+ */
+fn is_truthy(value: &ValueType) -> bool {
+    !matches!(value, ValueType::Nil | ValueType::Boolean(false))
+}
+
 fn is_equal(left: &ValueType, right: &ValueType) -> bool{
     match (left, right){
         (ValueType::Nil, ValueType::Nil) => true,
         (ValueType::Number(x), ValueType::Number(y)) => x == y, // TODO: == on double, sus
         (ValueType::Boolean(x), ValueType::Boolean(y)) => x == y,
         (ValueType::String(x), ValueType::String(y)) => x == y,
+        (ValueType::Callable(x), ValueType::Callable(y)) => Rc::ptr_eq(x, y),
+        (ValueType::Class(x), ValueType::Class(y)) => Rc::ptr_eq(x, y),
+        (ValueType::Instance(x), ValueType::Instance(y)) => Rc::ptr_eq(x, y),
+        // same list, not just the same elements
+        (ValueType::List(x), ValueType::List(y)) => Rc::ptr_eq(x, y),
         (_, _) => false
     }
 }
 
-fn unary_funct(operation: &Token, expression: &Expr) -> ValueType {
-    match operation {
+fn unary_funct(operation: &SpannedToken, expression: &Expr, environment: &Rc<RefCell<Environment>>) -> Result<ValueType, RuntimeError> {
+    match operation.token {
         Token::MINUS => {
-            let sub_expr_res = evaluate(expression);
+            let sub_expr_res = evaluate(expression, environment)?;
             match sub_expr_res {
-                ValueType::Number(x) => ValueType::Number(-x),
+                ValueType::Number(x) => Ok(ValueType::Number(-x)),
 
-                _ => panic!("Runtime error, expected number to unary - operator")
+                _ => runtime_error(operation, String::from("Expected number to unary - operator"))
             }
         },
         Token::BANG => {
-            let sub_expr_res = evaluate(expression);
-            match sub_expr_res {
-                ValueType::Boolean(b) => ValueType::Boolean(!b),
-                ValueType::Nil => ValueType::Boolean(true),
+            let sub_expr_res = evaluate(expression, environment)?;
+            Ok(ValueType::Boolean(!is_truthy(&sub_expr_res)))
+        }
+        ref other => runtime_error(operation, format!("Not supported unary expression: {:?}", other))
+    }
+}
 
-                _ => panic!("Runtime error, expected truthy value to unary ! operator")
-            }
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::environment::Environment;
+    use crate::interpreter::{global_environment, stmt_eval, RuntimeError, ValueType};
+    use crate::{parser, resolver, scanner};
+
+    /**
+     * Runs the source in a fresh environment and hands it back
+     */
+    fn run(source: &str) -> Result<Rc<RefCell<Environment>>, RuntimeError> {
+        let tokens = scanner::scan_tokens(source).expect("Should scan");
+        let statements = parser::program(tokens.into_iter()).expect("Should parse");
+        resolver::resolve(&statements).expect("Should resolve");
+
+        let environment = global_environment();
+        for statement in &statements {
+            stmt_eval(statement, &environment)?;
         }
-        other => panic!("Not supported unary expression: {:?}", other)
+        Ok(environment)
+    }
+
+    #[test]
+    fn global_variable_tests() {
+        let environment = run("var a = 1; var b = a + 2; var c; var a = \"again\";").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::String(String::from("again"))));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Number(3.0)));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Nil));
+
+        let error = run("var a = 1;\nprint a + nope;").unwrap_err();
+        assert_eq!(error.message, "Undefined variable 'nope'.");
+        assert_eq!(error.to_string(), "[line 2, column 11] Runtime error: Undefined variable 'nope'.");
+    }
+
+    #[test]
+    fn assignment_tests() {
+        let environment = run("var a = 1; var b; a = b = a + 1; a = a * 10;").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(20.0)));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Number(2.0)));
+
+        // has to be declared first
+        assert_eq!(run("nope = 1;").unwrap_err().message, "Undefined variable 'nope'.");
+    }
+
+    #[test]
+    fn block_scope_tests() {
+        let environment = run("var a = 1; var b = 2; { var a = 10; b = a + b; { var c = a; } }").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(1.0)));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Number(12.0)));
+        assert_eq!(environment.borrow().get("c"), None);
+
+        // the block's scope is gone even though it blew up halfway through
+        let tokens = scanner::scan_tokens("var a = 1; { var a = 2; a = a + nil; }").unwrap();
+        let statements = parser::program(tokens.into_iter()).unwrap();
+        resolver::resolve(&statements).unwrap();
+        let environment = global_environment();
+        stmt_eval(&statements[0], &environment).unwrap();
+        assert!(stmt_eval(&statements[1], &environment).is_err());
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(1.0)));
+        assert!(Rc::ptr_eq(&Environment::globals(&environment), &environment));
+    }
+
+    #[test]
+    fn control_flow_tests() {
+        let environment = run("var a; var b; if (1 > 2) a = 1; else a = 2; if (nil) b = 1;").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(2.0)));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Nil));
+
+        let environment = run("var sum = 0; var i = 0; while (i < 5) { i = i + 1; sum = sum + i; }").unwrap();
+        assert_eq!(environment.borrow().get("sum"), Some(ValueType::Number(15.0)));
+
+        // the loop variable doesn't leak out
+        let environment = run("var product = 1; for (var i = 1; i <= 4; i = i + 1) product = product * i;").unwrap();
+        assert_eq!(environment.borrow().get("product"), Some(ValueType::Number(24.0)));
+        assert_eq!(environment.borrow().get("i"), None);
+    }
+
+    #[test]
+    fn logical_tests() {
+        let environment = run("var a = nil or \"yes\"; var b = 0 and false; var c = nil and 1; var d = 1 or 2;").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::String(String::from("yes"))));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Boolean(false)));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Nil));
+        assert_eq!(environment.borrow().get("d"), Some(ValueType::Number(1.0)));
+
+        // the right side never runs, so the undefined variable is fine
+        let environment = run("var x = true or nope; var y = false and nope;").unwrap();
+        assert_eq!(environment.borrow().get("x"), Some(ValueType::Boolean(true)));
+        assert_eq!(environment.borrow().get("y"), Some(ValueType::Boolean(false)));
+
+        // ! goes by truthiness too, so only nil and false are falsey
+        let environment = run("var e = !\"a\"; var f = !0; var g = !nil;").unwrap();
+        assert_eq!(environment.borrow().get("e"), Some(ValueType::Boolean(false)));
+        assert_eq!(environment.borrow().get("f"), Some(ValueType::Boolean(false)));
+        assert_eq!(environment.borrow().get("g"), Some(ValueType::Boolean(true)));
+    }
+
+    #[test]
+    fn function_tests() {
+        let environment = run("
+            fun fib(n) {
+                if (n < 2) return n;
+                return fib(n - 1) + fib(n - 2);
+            }
+            var a = fib(10);
+
+            // return from inside nested loops and blocks
+            fun first_over(limit) {
+                for (var i = 0; i < 100; i = i + 1) {
+                    { while (true) { if (i * i > limit) return i; i = i + 1; } }
+                }
+            }
+            var b = first_over(50);
+
+            fun nothing() {}
+            var c = nothing();
+            var d = clock() > 0;
+        ").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(55.0)));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Number(8.0)));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Nil));
+        assert_eq!(environment.borrow().get("d"), Some(ValueType::Boolean(true)));
+
+        let error = run("fun f(a, b) {}\nf(1);").unwrap_err();
+        assert_eq!(error.to_string(), "[line 2, column 4] Runtime error: Expected 2 arguments but got 1.");
+        assert_eq!(run("\"not a function\"();").unwrap_err().message, "Can only call functions and classes.");
+
+        // errors come out of the call with their own position
+        let error = run("fun f() {\n  return nope;\n}\nf();").unwrap_err();
+        assert_eq!(error.to_string(), "[line 2, column 10] Runtime error: Undefined variable 'nope'.");
+    }
+
+    #[test]
+    fn closure_tests() {
+        let environment = run("
+            fun make_counter() {
+                var count = 0;
+                fun increment() {
+                    count = count + 1;
+                    return count;
+                }
+                return increment;
+            }
+            var counter = make_counter();
+            var other = make_counter();
+            counter();
+            counter();
+            var a = counter();
+            var b = other();
+
+            fun apply(f, x) { return f(x); }
+            fun twice(x) { return x * 2; }
+            var c = apply(twice, 21);
+        ").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::Number(3.0)));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::Number(1.0)));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Number(42.0)));
+
+        // show_a keeps seeing the global even after the block declares its own a
+        let environment = run("
+            var a = \"global\";
+            var first;
+            var second;
+            {
+                fun show_a() { return a; }
+                first = show_a();
+                var a = \"block\";
+                second = show_a();
+            }
+        ").unwrap();
+        assert_eq!(environment.borrow().get("first"), Some(ValueType::String(String::from("global"))));
+        assert_eq!(environment.borrow().get("second"), Some(ValueType::String(String::from("global"))));
+    }
+
+    #[test]
+    fn class_tests() {
+        let environment = run("
+            class Point {
+                init(x, y) {
+                    this.x = x;
+                    this.y = y;
+                }
+                sum() { return this.x + this.y; }
+                scaled(n) { return Point(this.x * n, this.y * n); }
+            }
+            var p = Point(1, 2);
+            var a = p.sum();
+            var b = p.scaled(10).sum();
+
+            // methods remember their instance after being pulled off it
+            var method = p.sum;
+            p.x = 5;
+            var c = method();
+
+            // fields shadow methods
+            p.sum = \"field\";
+            var d = p.sum;
+
+            // init returns this, even when called again or returning early
+            class Early { init() { this.ran = true; return; this.ran = false; } }
+            var e = Early();
+            var f = e.init() == e;
+            var g = e.ran;
+            var h = \"${Point} ${p}\";
+        ").unwrap();
+        let get = |name: &str| environment.borrow().get(name);
+        assert_eq!(get("a"), Some(ValueType::Number(3.0)));
+        assert_eq!(get("b"), Some(ValueType::Number(30.0)));
+        assert_eq!(get("c"), Some(ValueType::Number(7.0)));
+        assert_eq!(get("d"), Some(ValueType::String(String::from("field"))));
+        assert_eq!(get("f"), Some(ValueType::Boolean(true)));
+        assert_eq!(get("g"), Some(ValueType::Boolean(true)));
+        assert_eq!(get("h"), Some(ValueType::String(String::from("Point Point instance"))));
+
+        let error = run("class A {}\nA().nope;").unwrap_err();
+        assert_eq!(error.to_string(), "[line 2, column 5] Runtime error: Undefined property 'nope'.");
+        assert_eq!(run("var x = 1; x.y;").unwrap_err().message, "Only instances have properties.");
+        assert_eq!(run("var x = 1; x.y = 2;").unwrap_err().message, "Only instances have fields.");
+        assert_eq!(run("class A { init(a) {} } A();").unwrap_err().message, "Expected 1 arguments but got 0.");
+    }
+
+    #[test]
+    fn inheritance_tests() {
+        let environment = run("
+            class Animal {
+                init(name) { this.name = name; }
+                speak() { return this.name + \" makes a sound\"; }
+                kind() { return \"animal\"; }
+            }
+            class Dog < Animal {
+                speak() { return super.speak() + \", woof\"; }
+            }
+            class Puppy < Dog {
+                speak() { return super.speak() + \"!\"; }
+            }
+            var a = Puppy(\"Rex\").speak();
+            var b = Dog(\"Fido\").kind();
+        ").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::String(String::from("Rex makes a sound, woof!"))));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::String(String::from("animal"))));
+
+        let error = run("var NotAClass = 1;\nclass A < NotAClass {}").unwrap_err();
+        assert_eq!(error.to_string(), "[line 2, column 11] Runtime error: Superclass must be a class.");
+        assert_eq!(run("class A {} class B < A { f() { return super.nope; } } B().f();").unwrap_err().message,
+            "Undefined property 'nope'.");
+    }
+
+    #[test]
+    fn jump_tests() {
+        let environment = run("
+            var odd_sum = 0;
+            for (var i = 0; i < 10; i = i + 1) {
+                if (i == 7) break;
+                // the increment still runs after continue
+                { if (i == 2 or i == 4 or i == 6 or i == 0) continue; }
+                odd_sum = odd_sum + i;
+            }
+
+            var pairs = \"\";
+            outer: for (var a = 0; a < 3; a = a + 1) {
+                var b = 0;
+                while (true) {
+                    b = b + 1;
+                    if (b > a) continue outer;
+                    if (a == 2) break outer;
+                    pairs = pairs + \"${a}${b} \";
+                }
+            }
+
+            // a break inside a function only leaves the function's own loop
+            fun first_negative(n) {
+                while (true) {
+                    n = n - 1;
+                    if (n < 0) break;
+                }
+                return n;
+            }
+            var c = 0;
+            while (c < 3) { c = c + 1; first_negative(c); }
+        ").unwrap();
+        assert_eq!(environment.borrow().get("odd_sum"), Some(ValueType::Number(9.0)));
+        assert_eq!(environment.borrow().get("pairs"), Some(ValueType::String(String::from("11 "))));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Number(3.0)));
+    }
+
+    #[test]
+    fn conditional_and_comma_tests() {
+        let environment = run("
+            var a = 1 > 2 ? \"yes\" : \"no\";
+            var n = 15;
+            var b = n < 10 ? \"small\" : n < 20 ? \"medium\" : \"large\";
+            // the other branch never runs
+            var c = true ? 1 : nope;
+            var d = (a = \"first\", a + \" second\");
+            var e = 0;
+            for (var i = 0; i < 3; i = i + 1, e = e + 10) {}
+        ").unwrap();
+        assert_eq!(environment.borrow().get("a"), Some(ValueType::String(String::from("first"))));
+        assert_eq!(environment.borrow().get("b"), Some(ValueType::String(String::from("medium"))));
+        assert_eq!(environment.borrow().get("c"), Some(ValueType::Number(1.0)));
+        assert_eq!(environment.borrow().get("d"), Some(ValueType::String(String::from("first second"))));
+        assert_eq!(environment.borrow().get("e"), Some(ValueType::Number(30.0)));
+    }
+
+    #[test]
+    fn list_tests() {
+        let environment = run("
+            var xs = [1, 2, 3];
+            var a = xs[0] + xs[2];
+            xs[1] = \"two\";
+            var ys = xs;
+            push(ys, [4]);
+            var b = \"${xs}\";
+            var c = xs[3][0];
+            var d = len(xs) + len(\"héllo\");
+
+            var popped = pop(xs);
+            insert(xs, 0, 0);
+            insert(xs, len(xs), \"end\");
+            var removed = remove(xs, 1);
+            var e = \"${xs} ${popped} ${removed}\";
+
+            var f = \"${slice(xs, 1, 3)} ${slice(xs, 2, 2)}\";
+            var g = contains(xs, \"two\") and !contains(xs, 1);
+            var h = [] == [] or xs == ys;
+        ").unwrap();
+        let get = |name: &str| environment.borrow().get(name);
+        assert_eq!(get("a"), Some(ValueType::Number(4.0)));
+        assert_eq!(get("b"), Some(ValueType::String(String::from("[1, \"two\", 3, [4]]"))));
+        assert_eq!(get("c"), Some(ValueType::Number(4.0)));
+        assert_eq!(get("d"), Some(ValueType::Number(9.0)));
+        assert_eq!(get("e"), Some(ValueType::String(String::from("[0, \"two\", 3, \"end\"] [4] 1"))));
+        assert_eq!(get("f"), Some(ValueType::String(String::from("[\"two\", 3] []"))));
+        assert_eq!(get("g"), Some(ValueType::Boolean(true)));
+        assert_eq!(get("h"), Some(ValueType::Boolean(true)));
+
+        // a list inside itself doesn't recurse forever, a list in there twice is fine
+        let environment = run("
+            var xs = [1];
+            push(xs, xs);
+            var a = \"${xs}\";
+            var ys = [2];
+            var b = \"${[ys, ys]}\";
+        ").unwrap();
+        let get = |name: &str| environment.borrow().get(name);
+        assert_eq!(get("a"), Some(ValueType::String(String::from("[1, [...]]"))));
+        assert_eq!(get("b"), Some(ValueType::String(String::from("[[2], [2]]"))));
+    }
+
+    #[test]
+    fn list_error_tests() {
+        let error = run("var xs = [1, 2];\nxs[-1];").unwrap_err();
+        assert_eq!(error.to_string(), "[line 2, column 6] Runtime error: List index can't be negative, got -1.");
+        assert_eq!(run("[1, 2][2];").unwrap_err().message, "List index 2 is out of range for a list of length 2.");
+        assert_eq!(run("var xs = []; xs[0] = 1;").unwrap_err().message, "List index 0 is out of range for a list of length 0.");
+        assert_eq!(run("[1][0.5];").unwrap_err().message, "List index must be a whole number, got 0.5.");
+        assert_eq!(run("[1][\"0\"];").unwrap_err().message, "List index must be a number, got a string.");
+        assert_eq!(run("var x = 1; x[0];").unwrap_err().message, "Only lists can be indexed.");
+
+        assert_eq!(run("pop([]);").unwrap_err().message, "Can't pop from an empty list.");
+        assert_eq!(run("insert([1], 3, 0);").unwrap_err().message, "List index 3 is out of range for a list of length 1.");
+        assert_eq!(run("slice([1, 2], 2, 1);").unwrap_err().message, "Slice start 2 is after its end 1.");
+        assert_eq!(run("push(1, 2);").unwrap_err().message, "push() expects a list, got a number.");
+        assert_eq!(run("len(nil);").unwrap_err().message, "len() expects a list or string, got nil.");
     }
 }
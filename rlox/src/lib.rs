@@ -0,0 +1,8 @@
+pub mod scanner;
+pub mod byte_scanner;
+pub mod token_dump;
+pub mod highlight;
+pub mod parser;
+pub mod resolver;
+pub mod environment;
+pub mod interpreter;
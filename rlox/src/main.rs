@@ -1,42 +1,212 @@
+use std::cell::RefCell;
 use std::env;
-use std::io::{self, BufRead};
-mod scanner;
-mod parser;
-mod interpreter;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::rc::Rc;
+
+use rlox::environment::Environment;
+use rlox::highlight::{self, Format};
+use rlox::scanner::Token;
+use rlox::token_dump::{self, DumpFormat};
+use rlox::{interpreter, parser, resolver, scanner};
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    dbg!(&args);
 
-    match args.len(){
-        // case of one arg
-        2 => run_file(&args[1]),
+    match &args[1..] {
         // no arg
-        1 => run_prompt(),
-        _ => {
-            panic!("Usage: jlox [script]");
-        }
+        [] => run_prompt(),
+        [command, rest @ ..] if command == "tokens" => tokens_command(rest),
+        [command, rest @ ..] if command == "highlight" => highlight_command(rest),
+        // case of one arg
+        [path] => run_file(path),
+        _ => usage()
     };
     // println!("Hello, world!");
 }
 
+fn usage() -> ! {
+    eprintln!("Usage: rlox [script]\n       rlox tokens [--json] [script]\n       rlox highlight <script> [--format html|ansi]");
+    // EX_USAGE, like jlox
+    std::process::exit(64);
+}
+
+/**
+ * Bad command line that deserves more than the usage text
+ */
+fn cli_error(message: &str) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(64);
+}
+
+/**
+ * rlox highlight <script> [--format html|ansi], ansi by default
+ */
+fn highlight_command(args: &[String]) {
+    let mut path = None;
+    let mut format = Format::Ansi;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--format" {
+            format = match args.next().map(String::as_str) {
+                Some("ansi") => Format::Ansi,
+                Some("html") => Format::Html,
+                Some(other) => cli_error(&format!("Unknown format '{}', expected html or ansi.", other)),
+                None => cli_error("Expected html or ansi after --format."),
+            };
+        }
+        else if path.is_none() {
+            path = Some(arg);
+        }
+        else {
+            usage();
+        }
+    }
+    let Some(path) = path else {
+        cli_error("Expected a script to highlight.");
+    };
+
+    let source = std::fs::read_to_string(path)
+        .unwrap_or_else(|error| cli_error(&format!("Could not read '{}': {}", path, error)));
+    print!("{}", highlight::highlight(&source, format));
+}
+
+/**
+ * rlox tokens [--json] [script], dumps what the scanner makes of the script,
+ * or of each line typed in if there's no script
+ */
+fn tokens_command(args: &[String]) {
+    let (json, paths): (Vec<&String>, Vec<&String>) = args.iter().partition(|arg| *arg == "--json");
+    let format = || if json.is_empty() { DumpFormat::Text } else { DumpFormat::Json };
+
+    let dump = |source: &str| -> bool {
+        let dump = token_dump::dump_tokens(source, format());
+        print!("{}", dump.output);
+        if json.is_empty() {
+            for error in &dump.errors {
+                eprintln!("{}", error);
+            }
+        }
+        dump.errors.is_empty()
+    };
+
+    match paths[..] {
+        [] => {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                dump(&line.expect("Fuck bro idk stdin failed???"));
+            }
+        }
+        [path] => {
+            let source = std::fs::read_to_string(path).expect("Could not read from file");
+            if !dump(&source) {
+                std::process::exit(65);
+            }
+        }
+        _ => usage()
+    }
+}
+
 fn run_prompt() {
+    // variables stick around between lines
+    let environment = interpreter::global_environment();
+
+    let mut source = String::new();
     let stdin = io::stdin();
     for line in stdin.lock().lines() {
-        run(&line.expect("Fuck bro idk stdin failed???"));
+        let line = line.expect("Fuck bro idk stdin failed???");
+        source.push_str(&line);
+        source.push('\n');
+
+        // a blank line runs it anyway, so the errors get shown
+        if !line.trim().is_empty() && unfinished(&source) {
+            continue;
+        }
+        // errors already reported, just keep going with the next line
+        let _ = run(scanner::Scanner::new(&source), &environment);
+        source.clear();
+    }
+    if !source.is_empty() {
+        let _ = run(scanner::Scanner::new(&source), &environment);
+    }
+}
+
+/**
+ * Whether the REPL should wait for more lines before running `source`,
+ * because it stops part way through a string, comment, block and so on
+ */
+fn unfinished(source: &str) -> bool {
+    match scanner::scan_tokens(source) {
+        Err(errors) => errors.iter().any(|error| error.is_unfinished()),
+        Ok(tokens) => match parser::program(tokens.into_iter()) {
+            Err(errors) => errors.iter().any(|error| error.token.token == Token::EOF),
+            Ok(_) => false,
+        },
+    }
+}
+
+fn run_file(path: &str) {
+    let file = File::open(path).expect("Could not read from file");
+    let scanner = scanner::ReaderScanner::new(BufReader::new(file));
+
+    match run(scanner, &interpreter::global_environment()) {
+        Ok(()) => {},
+        // same exit codes as jlox
+        Err(RunError::Static) => std::process::exit(65),
+        Err(RunError::Runtime) => std::process::exit(70),
     }
 }
 
-fn run_file(path: &String) {
-    run(&std::fs::read_to_string(path).expect("Could not read from file"));
+enum RunError {
+    // scan or parse errors, nothing got run
+    Static,
+    Runtime,
 }
 
-fn run(source: &String){
-    let token_list = scanner::scan_tokens(&source);
-    let statements = parser::program(&mut token_list.iter().peekable());
+/**
+ * Parses the tokens as the scanner hands them over. Returns Err if there
+ * were errors, after printing them all to stderr
+ */
+fn run(scanner: impl Iterator<Item = Result<scanner::SpannedToken, scanner::ScanError>>, environment: &Rc<RefCell<Environment>>) -> Result<(), RunError> {
+    let mut scan_errors = Vec::new();
+    let tokens = scanner.filter_map(|item| match item {
+        Ok(token) => Some(token),
+        Err(error) => {
+            scan_errors.push(error);
+            None
+        }
+    });
+    let parsed = parser::program(tokens);
+
+    // parse errors after a scan error are likely just knock-on ones
+    if !scan_errors.is_empty() {
+        for error in scan_errors {
+            eprintln!("{}", error);
+        }
+        return Err(RunError::Static);
+    }
+    let statements = match parsed {
+        Ok(statements) => statements,
+        Err(errors) => {
+            for error in errors {
+                eprintln!("{}", error);
+            }
+            return Err(RunError::Static);
+        }
+    };
+    if let Err(errors) = resolver::resolve(&statements) {
+        for error in errors {
+            eprintln!("{}", error);
+        }
+        return Err(RunError::Static);
+    }
     //println!("{:?}", interpreter::stringify_valuetype(&interpreter::stmt_eval(&statements)));
 
     for statement in statements {
-        interpreter::stmt_eval(&statement);
+        if let Err(error) = interpreter::stmt_eval(&statement, environment) {
+            eprintln!("{}", error);
+            return Err(RunError::Runtime);
+        }
     }
+    Ok(())
 }
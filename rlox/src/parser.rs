@@ -1,13 +1,78 @@
-use std::iter::Peekable;
+use std::fmt;
+use std::cell::Cell;
+use std::collections::VecDeque;
+use std::rc::Rc;
 
-pub use crate::scanner::Token;
+pub use crate::scanner::{SpannedToken, StringSegment, Token};
+
+/**
+ * Where the parser pulls its tokens from, one at a time so a file can be
+ * parsed while it's still being scanned. Looks up to two tokens ahead.
+ */
+struct TokenIter<'a> {
+    tokens: Box<dyn Iterator<Item = SpannedToken> + 'a>,
+    peeked: VecDeque<SpannedToken>,
+}
+
+impl<'a> TokenIter<'a> {
+    fn new(tokens: impl Iterator<Item = SpannedToken> + 'a) -> Self {
+        TokenIter { tokens: Box::new(tokens), peeked: VecDeque::new() }
+    }
+
+    fn peek_nth(&mut self, n: usize) -> Option<&SpannedToken> {
+        while self.peeked.len() <= n {
+            self.peeked.push_back(self.tokens.next()?);
+        }
+        self.peeked.get(n)
+    }
+}
+
+impl Iterator for TokenIter<'_> {
+    type Item = SpannedToken;
+
+    fn next(&mut self) -> Option<SpannedToken> {
+        self.peeked.pop_front().or_else(|| self.tokens.next())
+    }
+}
+
+/**
+ * How many scopes out from the current one a variable lives, filled in by the
+ * resolver after parsing. Still None afterwards means it's a global.
+ */
+pub type Depth = Cell<Option<usize>>;
 
 #[derive(PartialEq, Debug, Clone)]
 pub enum Expr {
     //nonterminals
-    Binary(Box<Expr>, Token, Box<Expr>),
+    Binary(Box<Expr>, SpannedToken, Box<Expr>),
+    // and/or, separate from Binary since the right side might not run
+    Logical(Box<Expr>, SpannedToken, Box<Expr>),
+    // condition ? then : else
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    // a, b runs a and throws it away
+    Comma(Box<Expr>, Box<Expr>),
     Grouping(Box<Expr>),
-    Unary(Token, Box<Expr>),
+    Unary(SpannedToken, Box<Expr>),
+    Variable(SpannedToken, Depth),
+    // name and new value
+    Assign(SpannedToken, Box<Expr>, Depth),
+    // callee, closing paren for errors, arguments
+    Call(Box<Expr>, SpannedToken, Vec<Expr>),
+    // object and property name
+    Get(Box<Expr>, SpannedToken),
+    // object, property name and new value
+    Set(Box<Expr>, SpannedToken, Box<Expr>),
+    This(SpannedToken, Depth),
+    // [a, b, c]
+    List(Vec<Expr>),
+    // list, closing bracket for errors, index
+    Index(Box<Expr>, SpannedToken, Box<Expr>),
+    // list, closing bracket, index and new value
+    SetIndex(Box<Expr>, SpannedToken, Box<Expr>, Box<Expr>),
+    // super keyword and method name
+    Super(SpannedToken, SpannedToken, Depth),
+    // pieces to stringify and glue together, from "a ${b} c"
+    Interpolation(Vec<Expr>),
     //terminals
     String(String),
     Number(f64),
@@ -19,38 +84,364 @@ pub enum Expr {
 pub enum Stmt {
     Expression(Expr),
     Print(Expr),
+    // name and initializer
+    Var(SpannedToken, Option<Expr>),
+    Block(Vec<Stmt>),
+    // condition, then branch, else branch
+    If(Expr, Box<Stmt>, Option<Box<Stmt>>),
+    // label, condition, body, and for loops' increment which has to run
+    // after a continue too
+    While(Option<SpannedToken>, Expr, Box<Stmt>, Option<Expr>),
+    // keyword and label
+    Break(SpannedToken, Option<SpannedToken>),
+    Continue(SpannedToken, Option<SpannedToken>),
+    // shared so every function value made from it can point at the body
+    Function(Rc<Function>),
+    // name, superclass (always a Variable) and methods
+    Class(SpannedToken, Option<Expr>, Vec<Rc<Function>>),
+    // the return keyword and value
+    Return(SpannedToken, Option<Expr>),
+}
+
+#[derive(PartialEq, Debug)]
+pub struct Function {
+    pub name: SpannedToken,
+    pub params: Vec<SpannedToken>,
+    pub body: Vec<Stmt>,
+}
+
+// same limit as jlox, keeps things sane for the future bytecode version
+const MAX_ARGUMENTS: usize = 255;
+
+/**
+ * Syntax error, `token` is the one the parser choked on
+ */
+#[derive(PartialEq, Debug, Clone)]
+pub struct ParseError {
+    pub message: String,
+    pub token: SpannedToken,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_error_at(f, &self.token, &self.message)
+    }
 }
 
 /**
- * program        → statement* EOF ;
+ * How errors found before running get shown, the resolver's ones too
+ */
+pub(crate) fn fmt_error_at(f: &mut fmt::Formatter<'_>, token: &SpannedToken, message: &str) -> fmt::Result {
+    match token.token {
+        Token::EOF => write!(f, "[{}] Error at end: {}", token.span, message),
+        ref other => write!(f, "[{}] Error at '{}': {}", token.span, other, message),
+    }
+}
 
-    statement      → exprStmt
-                | printStmt ;
+/**
+ * The parser only ever puts IDENTIFIER tokens where a name goes
+ */
+pub(crate) fn variable_name(name: &SpannedToken) -> &str {
+    match &name.token {
+        Token::IDENTIFIER(name) => name,
+        other => panic!("Expected identifier as variable name, got {:?}", other)
+    }
+}
+
+fn error<T>(token: &SpannedToken, message: &str) -> Result<T, ParseError> {
+    Err(ParseError { message: message.to_string(), token: token.clone() })
+}
 
-    exprStmt       → expression ";" ;
-    printStmt      → "print" expression ";" ;
+/**
+ * The EOF token never gets consumed, so there's always something to peek at
+ */
+fn peek<'a>(iter: &'a mut TokenIter<'_>) -> &'a SpannedToken {
+    iter.peek_nth(0).expect("Iterator should not be exhausted")
+}
+
+/**
+ * Consumes the next token if it's `expected`, otherwise errors with `message`
  */
-pub fn program(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Vec<Stmt> {
+fn consume(iter: &mut TokenIter<'_>, expected: Token, message: &str) -> Result<SpannedToken, ParseError> {
+    if peek(iter).token == expected {
+        Ok(iter.next().expect("We just peeked"))
+    }
+    else {
+        error(peek(iter), message)
+    }
+}
+
+/**
+ * Consumes the next token if it's a name, otherwise errors with `message`
+ */
+fn consume_identifier(iter: &mut TokenIter<'_>, message: &str) -> Result<SpannedToken, ParseError> {
+    match peek(iter).token {
+        Token::IDENTIFIER(_) => Ok(iter.next().expect("We just peeked")),
+        _ => error(peek(iter), message)
+    }
+}
+
+/**
+ * program        → declaration* EOF ;
+ *
+ * declaration    → classDecl
+ *                | funDecl
+ *                | varDecl
+ *                | statement ;
+ *
+ * classDecl      → "class" IDENTIFIER ( "<" IDENTIFIER )?
+ *                  "{" function* "}" ;
+ * funDecl        → "fun" function ;
+ * function       → IDENTIFIER "(" parameters? ")" block ;
+ * parameters     → IDENTIFIER ( "," IDENTIFIER )* ;
+ *
+ * varDecl        → "var" IDENTIFIER ( "=" expression )? ";" ;
+ *
+ * statement      → exprStmt
+ *                | forStmt
+ *                | ifStmt
+ *                | printStmt
+ *                | returnStmt
+ *                | breakStmt
+ *                | continueStmt
+ *                | ( IDENTIFIER ":" )? ( whileStmt | forStmt )
+ *                | block ;
+ *
+ * exprStmt       → expression ";" ;
+ * forStmt        → "for" "(" ( varDecl | exprStmt | ";" )
+ *                  expression? ";"
+ *                  expression? ")" statement ;
+ * ifStmt         → "if" "(" expression ")" statement
+ *                  ( "else" statement )? ;
+ * printStmt      → "print" expression ";" ;
+ * returnStmt     → "return" expression? ";" ;
+ * breakStmt      → "break" IDENTIFIER? ";" ;
+ * continueStmt   → "continue" IDENTIFIER? ";" ;
+ * whileStmt      → "while" "(" expression ")" statement ;
+ * block          → "{" declaration* "}" ;
+ *
+ * After a syntax error it skips ahead to the next statement and carries on,
+ * so every error in the file gets reported in one go.
+ */
+pub fn program(tokens: impl Iterator<Item = SpannedToken>) -> Result<Vec<Stmt>, Vec<ParseError>> {
+    let iter = &mut TokenIter::new(tokens);
     let mut result = Vec::new();
+    let mut errors = Vec::new();
 
     loop {
-        let x = iter.peek().expect("Iterator should not be exhausted");
-        if let Token::EOF = x {
+        let x = peek(iter);
+        if let Token::EOF = x.token {
             break;
         }
-        result.push(statement(iter));
+        if let Some(stmt) = declaration(iter, &mut errors) {
+            result.push(stmt);
+        }
+    };
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    Ok(result)
+}
+
+/**
+ * Skips tokens until what's probably the start of the next statement,
+ * i.e. just past a ; or at a keyword that begins a statement
+ */
+fn synchronize(iter: &mut TokenIter<'_>) {
+    loop {
+        match peek(iter).token {
+            Token::EOF |
+            Token::CLASS | Token::FUN | Token::VAR | Token::FOR |
+            Token::IF | Token::WHILE | Token::PRINT | Token::RETURN |
+            Token::BREAK | Token::CONTINUE => return,
+            Token::SEMICOLON => {
+                iter.next();
+                return;
+            }
+            _ => { iter.next(); }
+        }
+    }
+}
+
+/**
+ * Like jlox, a syntax error gets recorded in `errors` and skipped past
+ * right here, so the block it's in can carry on with the next statement
+ */
+fn declaration(iter: &mut TokenIter<'_>, errors: &mut Vec<ParseError>) -> Option<Stmt> {
+    let result = match peek(iter).token {
+        Token::VAR => {
+            // consume var token
+            iter.next();
+            varDecl(iter)
+        }
+        Token::FUN => {
+            iter.next();
+            function(iter, "function", errors).map(|function| Stmt::Function(Rc::new(function)))
+        }
+        Token::CLASS => {
+            iter.next();
+            classDecl(iter, errors)
+        }
+        _ => statement(iter, errors)
+    };
+
+    match result {
+        Ok(stmt) => Some(stmt),
+        Err(error) => {
+            errors.push(error);
+            synchronize(iter);
+            None
+        }
+    }
+}
+
+#[allow(non_snake_case)]
+fn varDecl(iter: &mut TokenIter<'_>) -> Result<Stmt, ParseError> {
+    let name = consume_identifier(iter, "Expect variable name.")?;
+
+    let initializer = match peek(iter).token {
+        Token::EQUAL => {
+            iter.next();
+            Some(expression(iter)?)
+        }
+        _ => None
     };
 
-    result
+    consume(iter, Token::SEMICOLON, "Expect ';' after variable declaration.")?;
+    Ok(Stmt::Var(name, initializer))
+}
+
+#[allow(non_snake_case)]
+fn classDecl(iter: &mut TokenIter<'_>, errors: &mut Vec<ParseError>) -> Result<Stmt, ParseError> {
+    let name = consume_identifier(iter, "Expect class name.")?;
+
+    let superclass = match peek(iter).token {
+        Token::LESS => {
+            iter.next();
+            let superclass = consume_identifier(iter, "Expect superclass name.")?;
+            Some(Expr::Variable(superclass, Depth::default()))
+        }
+        _ => None
+    };
+
+    consume(iter, Token::LEFT_BRACE, "Expect '{' before class body.")?;
+    let mut methods = Vec::new();
+    while !matches!(peek(iter).token, Token::RIGHT_BRACE | Token::EOF) {
+        match function(iter, "method", errors) {
+            Ok(method) => methods.push(Rc::new(method)),
+            Err(error) => {
+                errors.push(error);
+                skip_member(iter);
+            }
+        }
+    }
+    consume(iter, Token::RIGHT_BRACE, "Expect '}' after class body.")?;
+
+    Ok(Stmt::Class(name, superclass, methods))
+}
+
+/**
+ * Skips the rest of a class member that didn't parse, up to just past its
+ * ; or its body, or to the } that closes the class
+ */
+fn skip_member(iter: &mut TokenIter<'_>) {
+    let mut depth = 0;
+    loop {
+        match peek(iter).token {
+            Token::EOF => return,
+            Token::RIGHT_BRACE if depth == 0 => return,
+            Token::SEMICOLON if depth == 0 => {
+                iter.next();
+                return;
+            }
+            Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_BRACE => {
+                depth -= 1;
+                if depth == 0 {
+                    iter.next();
+                    return;
+                }
+            }
+            _ => {}
+        }
+        iter.next();
+    }
+}
+
+/**
+ * Functions and methods look the same, `kind` is just for the error messages
+ */
+fn function(iter: &mut TokenIter<'_>, kind: &str, errors: &mut Vec<ParseError>) -> Result<Function, ParseError> {
+    let name = consume_identifier(iter, &format!("Expect {} name.", kind))?;
+
+    consume(iter, Token::LEFT_PAREN, &format!("Expect '(' after {} name.", kind))?;
+    let mut params = Vec::new();
+    if peek(iter).token != Token::RIGHT_PAREN {
+        loop {
+            if params.len() >= MAX_ARGUMENTS {
+                return error(peek(iter), "Can't have more than 255 parameters.");
+            }
+            params.push(consume_identifier(iter, "Expect parameter name.")?);
+
+            if peek(iter).token != Token::COMMA {
+                break;
+            }
+            iter.next();
+        }
+    }
+    consume(iter, Token::RIGHT_PAREN, "Expect ')' after parameters.")?;
+
+    consume(iter, Token::LEFT_BRACE, &format!("Expect '{{' before {} body.", kind))?;
+    let body = block(iter, errors)?;
+    Ok(Function { name, params, body })
 }
 
-fn statement(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Stmt {
-    match iter.peek().expect("Iterator should not be exhausted") {
+fn statement(iter: &mut TokenIter<'_>, errors: &mut Vec<ParseError>) -> Result<Stmt, ParseError> {
+    let labelled = matches!(peek(iter).token, Token::IDENTIFIER(_))
+        && iter.peek_nth(1).is_some_and(|next| next.token == Token::COLON);
+
+    match peek(iter).token {
         Token::PRINT => {
             // consume print token
             iter.next();
             printStmt(iter)
         }
+        Token::LEFT_BRACE => {
+            iter.next();
+            Ok(Stmt::Block(block(iter, errors)?))
+        }
+        Token::IF => {
+            iter.next();
+            ifStmt(iter, errors)
+        }
+        Token::WHILE => {
+            iter.next();
+            whileStmt(iter, None, errors)
+        }
+        Token::FOR => {
+            iter.next();
+            forStmt(iter, None, errors)
+        }
+        Token::RETURN => returnStmt(iter),
+        Token::BREAK | Token::CONTINUE => jumpStmt(iter),
+        // outer: while (...)
+        Token::IDENTIFIER(_) if labelled => {
+            let label = iter.next().expect("We just peeked");
+            iter.next();
+
+            match peek(iter).token {
+                Token::WHILE => {
+                    iter.next();
+                    whileStmt(iter, Some(label), errors)
+                }
+                Token::FOR => {
+                    iter.next();
+                    forStmt(iter, Some(label), errors)
+                }
+                _ => error(peek(iter), "Expect loop after label.")
+            }
+        }
         _ => {
             exprStmt(iter)
         }
@@ -58,23 +449,137 @@ fn statement(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Stmt {
 }
 
 #[allow(non_snake_case)]
-fn printStmt(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Stmt {
-    let expr = expression(iter);
+fn printStmt(iter: &mut TokenIter<'_>) -> Result<Stmt, ParseError> {
+    let expr = expression(iter)?;
+
+    consume(iter, Token::SEMICOLON, "Expect ';' after value.")?;
+    Ok(Stmt::Print(expr))
+}
+
+#[allow(non_snake_case)]
+fn returnStmt(iter: &mut TokenIter<'_>) -> Result<Stmt, ParseError> {
+    // keep the keyword around for error reporting
+    let keyword = iter.next().expect("We just peeked");
 
-    match iter.next().expect("Iterator should not be empty"){
-        Token::SEMICOLON => Stmt::Print(expr),
-        other => panic!("Bad token after expression, {:?}", other)
+    let value = match peek(iter).token {
+        Token::SEMICOLON => None,
+        _ => Some(expression(iter)?)
+    };
+
+    consume(iter, Token::SEMICOLON, "Expect ';' after return value.")?;
+    Ok(Stmt::Return(keyword, value))
+}
+
+/**
+ * break and continue, whether there's a loop to jump out of is the
+ * resolver's problem
+ */
+#[allow(non_snake_case)]
+fn jumpStmt(iter: &mut TokenIter<'_>) -> Result<Stmt, ParseError> {
+    let keyword = iter.next().expect("We just peeked");
+
+    let label = match peek(iter).token {
+        Token::IDENTIFIER(_) => Some(iter.next().expect("We just peeked")),
+        _ => None
+    };
+
+    consume(iter, Token::SEMICOLON, &format!("Expect ';' after '{}'.", keyword.token))?;
+    match keyword.token {
+        Token::BREAK => Ok(Stmt::Break(keyword, label)),
+        _ => Ok(Stmt::Continue(keyword, label)),
     }
 }
 
 #[allow(non_snake_case)]
-fn exprStmt(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Stmt {
-    let expr = expression(iter);
+fn ifStmt(iter: &mut TokenIter<'_>, errors: &mut Vec<ParseError>) -> Result<Stmt, ParseError> {
+    consume(iter, Token::LEFT_PAREN, "Expect '(' after 'if'.")?;
+    let condition = expression(iter)?;
+    consume(iter, Token::RIGHT_PAREN, "Expect ')' after if condition.")?;
+
+    let then_branch = statement(iter, errors)?;
+    // a dangling else goes with the nearest if
+    let else_branch = match peek(iter).token {
+        Token::ELSE => {
+            iter.next();
+            Some(Box::new(statement(iter, errors)?))
+        }
+        _ => None
+    };
+
+    Ok(Stmt::If(condition, Box::new(then_branch), else_branch))
+}
+
+#[allow(non_snake_case)]
+fn whileStmt(iter: &mut TokenIter<'_>, label: Option<SpannedToken>, errors: &mut Vec<ParseError>) -> Result<Stmt, ParseError> {
+    consume(iter, Token::LEFT_PAREN, "Expect '(' after 'while'.")?;
+    let condition = expression(iter)?;
+    consume(iter, Token::RIGHT_PAREN, "Expect ')' after condition.")?;
+
+    Ok(Stmt::While(label, condition, Box::new(statement(iter, errors)?), None))
+}
+
+/**
+ * No for node, it gets desugared into
+ * { initializer; while (condition) body; } with the increment kept on the
+ * while so continue doesn't skip it
+ */
+#[allow(non_snake_case)]
+fn forStmt(iter: &mut TokenIter<'_>, label: Option<SpannedToken>, errors: &mut Vec<ParseError>) -> Result<Stmt, ParseError> {
+    consume(iter, Token::LEFT_PAREN, "Expect '(' after 'for'.")?;
+
+    let initializer = match peek(iter).token {
+        Token::SEMICOLON => {
+            iter.next();
+            None
+        }
+        Token::VAR => {
+            iter.next();
+            Some(varDecl(iter)?)
+        }
+        _ => Some(exprStmt(iter)?)
+    };
+
+    let condition = match peek(iter).token {
+        Token::SEMICOLON => Expr::Boolean(true),
+        _ => expression(iter)?
+    };
+    consume(iter, Token::SEMICOLON, "Expect ';' after loop condition.")?;
+
+    let increment = match peek(iter).token {
+        Token::RIGHT_PAREN => None,
+        _ => Some(expression(iter)?)
+    };
+    consume(iter, Token::RIGHT_PAREN, "Expect ')' after for clauses.")?;
+
+    let mut body = Stmt::While(label, condition, Box::new(statement(iter, errors)?), increment);
+    if let Some(initializer) = initializer {
+        body = Stmt::Block(vec![initializer, body]);
+    }
+    Ok(body)
+}
+
+/**
+ * The statements up to the closing }, the { is already consumed
+ */
+fn block(iter: &mut TokenIter<'_>, errors: &mut Vec<ParseError>) -> Result<Vec<Stmt>, ParseError> {
+    let mut statements = Vec::new();
 
-    match iter.next().expect("Iterator should not be empty"){
-        Token::SEMICOLON => Stmt::Expression(expr),
-        other => panic!("Bad token after expression, {:?}", other)
+    while !matches!(peek(iter).token, Token::RIGHT_BRACE | Token::EOF) {
+        if let Some(stmt) = declaration(iter, errors) {
+            statements.push(stmt);
+        }
     }
+
+    consume(iter, Token::RIGHT_BRACE, "Expect '}' after block.")?;
+    Ok(statements)
+}
+
+#[allow(non_snake_case)]
+fn exprStmt(iter: &mut TokenIter<'_>) -> Result<Stmt, ParseError> {
+    let expr = expression(iter)?;
+
+    consume(iter, Token::SEMICOLON, "Expect ';' after expression.")?;
+    Ok(Stmt::Expression(expr))
 }
 
 
@@ -82,277 +587,471 @@ fn exprStmt(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Stmt {
 
 /**
  * Expression grammar impl
+ *
+ * expression     → comma ;
+ * comma          → assignment ( "," assignment )* ;
+ * assignment     → ( call "." )? IDENTIFIER "=" assignment
+ *                | call "[" expression "]" "=" assignment
+ *                | conditional ;
+ * conditional    → logic_or ( "?" expression ":" conditional )? ;
+ * logic_or       → logic_and ( "or" logic_and )* ;
+ * logic_and      → equality ( "and" equality )* ;
+ * equality       → comparison ( ( "!=" | "==" ) comparison )* ;
+ * comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+ * term           → factor ( ( "-" | "+" ) factor )* ;
+ * factor         → unary ( ( "/" | "*" ) unary )* ;
+ * unary          → ( "!" | "-" ) unary
+ *                | call ;
+ * call           → primary ( "(" arguments? ")" | "." IDENTIFIER
+ *                          | "[" expression "]" )* ;
+ * arguments      → assignment ( "," assignment )* ;
+ * primary        → NUMBER | STRING | INTERPOLATED | "true" | "false" | "nil"
+ *                | "this" | "(" expression ")" | IDENTIFIER
+ *                | "super" "." IDENTIFIER
+ *                | "[" ( assignment ( "," assignment )* ","? )? "]"
+ *                // error productions, a binary operator missing its left side
+ *                | ( "!=" | "==" ) comparison
+ *                | ( ">" | ">=" | "<" | "<=" ) term
+ *                | "+" factor
+ *                | ( "/" | "*" ) unary
+ *                | "and" equality
+ *                | "or" logic_and
+ *                | "?" conditional
+ *                | "," assignment ;
  */
-fn expression(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    equality(iter)
+fn expression(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    comma(iter)
 }
+
+fn comma(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = assignment(iter)?;
+
+    while matches!(peek(iter).token, Token::COMMA) {
+        iter.next();
+        result = Expr::Comma(Box::new(result), Box::new(assignment(iter)?))
+    }
+    Ok(result)
+}
+
 /**
- * expression     → equality ;
-    equality       → comparison ( ( "!=" | "==" ) comparison )* ;
-    comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
-    term           → factor ( ( "-" | "+" ) factor )* ;
-    factor         → unary ( ( "/" | "*" ) unary )* ;
-    unary          → ( "!" | "-" ) unary
-                | primary ;
-    primary        → NUMBER | STRING | "true" | "false" | "nil"
-                | "(" expression ")" ;
+ * Parses the left side as a normal expression first since we can't know it's
+ * an assignment till we hit the =, then checks it's something assignable
  */
+fn assignment(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let target = conditional(iter)?;
+
+    if peek(iter).token != Token::EQUAL {
+        return Ok(target);
+    }
+    let equals = iter.next().expect("We just peeked");
+
+    // right associative, a = b = c is a = (b = c)
+    let value = assignment(iter)?;
+
+    match target {
+        Expr::Variable(name, depth) => Ok(Expr::Assign(name, Box::new(value), depth)),
+        Expr::Get(object, name) => Ok(Expr::Set(object, name, Box::new(value))),
+        Expr::Index(list, bracket, index) => Ok(Expr::SetIndex(list, bracket, index, Box::new(value))),
+        _ => error(&equals, "Invalid assignment target.")
+    }
+}
+
+// right associative, a ? b : c ? d : e is a ? b : (c ? d : e)
+fn conditional(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let condition = logic_or(iter)?;
+
+    if peek(iter).token != Token::QUESTION {
+        return Ok(condition);
+    }
+    iter.next();
+
+    let then_branch = expression(iter)?;
+    consume(iter, Token::COLON, "Expect ':' after then branch of conditional expression.")?;
+    let else_branch = conditional(iter)?;
+
+    Ok(Expr::Conditional(Box::new(condition), Box::new(then_branch), Box::new(else_branch)))
+}
+
+fn logic_or(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = logic_and(iter)?;
+
+    while matches!(peek(iter).token, Token::OR) {
+        result = Expr::Logical(Box::new(result),
+            iter.next().expect("We just peeked"),
+            Box::new(logic_and(iter)?))
+    }
+    Ok(result)
+}
 
-fn equality(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let mut result = comparison(iter);
+fn logic_and(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = equality(iter)?;
 
-    loop
+    while matches!(peek(iter).token, Token::AND) {
+        result = Expr::Logical(Box::new(result),
+            iter.next().expect("We just peeked"),
+            Box::new(equality(iter)?))
+    }
+    Ok(result)
+}
+
+fn equality(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = comparison(iter)?;
+
+    while matches!(peek(iter).token,
+                   Token::BANG_EQUAL | Token::EQUAL_EQUAL)
     {
-        match iter.peek().expect("Iterator should not be exhausted") {
-            Token::BANG_EQUAL | Token::EQUAL_EQUAL =>
-            {
-                result = Expr::Binary(Box::new(result), 
-                    iter.next().expect("We just peeked").clone(), 
-                    Box::new(comparison(iter)))
-            },
-            _ => break
-        }
+        result = Expr::Binary(Box::new(result), 
+            iter.next().expect("We just peeked"), 
+            Box::new(comparison(iter)?))
     }
-    result
+    Ok(result)
 }
 
 // >, <, >= and <=
-fn comparison(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let mut result = term(iter);
+fn comparison(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = term(iter)?;
 
-    loop
+    while matches!(peek(iter).token,
+                   Token::GREATER | Token::GREATER_EQUAL |
+                   Token::LESS | Token::LESS_EQUAL)
     {
-        match iter.peek().expect("Iterator should not be exhausted") {
-            Token::GREATER | Token::GREATER_EQUAL |
-            Token::LESS | Token::LESS_EQUAL =>
-            {
-                result = Expr::Binary(Box::new(result), 
-                    iter.next().expect("We just peeked").clone(), 
-                    Box::new(term(iter)))
-            },
-            _ => break
-        }
+        result = Expr::Binary(Box::new(result), 
+            iter.next().expect("We just peeked"), 
+            Box::new(term(iter)?))
     }
-    result
+    Ok(result)
 }
 
 // + and -
-fn term(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let mut result = factor(iter);
+fn term(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = factor(iter)?;
 
-    loop
+    while matches!(peek(iter).token,
+                   Token::PLUS | Token::MINUS)
     {
-        match iter.peek().expect("Iterator should not be exhausted") {
-            Token::PLUS | Token::MINUS =>
-            {
-                result = Expr::Binary(Box::new(result), 
-                    iter.next().expect("We just peeked").clone(), 
-                    Box::new(factor(iter)))
-            },
-            _ => break
-        }
+        result = Expr::Binary(Box::new(result), 
+            iter.next().expect("We just peeked"), 
+            Box::new(factor(iter)?))
     }
-    result
+    Ok(result)
 }
 
 
 // * and /
-fn factor(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let mut result = unary(iter);
+fn factor(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = unary(iter)?;
 
-    loop
+    while matches!(peek(iter).token,
+                   Token::STAR | Token::SLASH)
     {
-        match iter.peek().expect("Iterator should not be exhausted") {
-            Token::STAR | Token::SLASH =>
-            {
-                result = Expr::Binary(Box::new(result), 
-                    iter.next().expect("We just peeked").clone(), 
-                    Box::new(unary(iter)))
-            },
-            _ => break
-        }
+        result = Expr::Binary(Box::new(result), 
+            iter.next().expect("We just peeked"), 
+            Box::new(unary(iter)?))
     }
-    result
+    Ok(result)
 }
 
 // - and !
-fn unary(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let next_token = iter.peek().expect("Iterator should not be exhausted");
-
-    match next_token {
+fn unary(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    match peek(iter).token {
         Token::MINUS | Token::BANG => 
         {
-            Expr::Unary(iter.next().expect("We just peeked").clone(), Box::new(unary(iter)))
+            Ok(Expr::Unary(iter.next().expect("We just peeked"), Box::new(unary(iter)?)))
         }
-        _ => primary(iter)
+        _ => call(iter)
     }
 }
 
-fn primary(iter: &mut Peekable<std::slice::Iter<'_, Token>>) -> Expr {
-    let next_token = iter.next().expect("Iterator should not be exhausted").clone();
+fn call(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let mut result = primary(iter)?;
 
-    match next_token {
-        Token::NUMBER(x) => Expr::Number(x),
-        Token::STRING(str) => Expr::String(str),
+    // f(1)(2) calls whatever f(1) returned
+    loop {
+        match peek(iter).token {
+            Token::LEFT_PAREN => {
+                iter.next();
+                result = finish_call(iter, result)?;
+            }
+            Token::DOT => {
+                iter.next();
+                let name = consume_identifier(iter, "Expect property name after '.'.")?;
+                result = Expr::Get(Box::new(result), name);
+            }
+            Token::LEFT_BRACKET => {
+                iter.next();
+                let index = expression(iter)?;
+                let bracket = consume(iter, Token::RIGHT_BRACKET, "Expect ']' after index.")?;
+                result = Expr::Index(Box::new(result), bracket, Box::new(index));
+            }
+            _ => return Ok(result)
+        }
+    }
+}
+
+fn finish_call(iter: &mut TokenIter<'_>, callee: Expr) -> Result<Expr, ParseError> {
+    let mut arguments = Vec::new();
+    if peek(iter).token != Token::RIGHT_PAREN {
+        loop {
+            if arguments.len() >= MAX_ARGUMENTS {
+                return error(peek(iter), "Can't have more than 255 arguments.");
+            }
+            // no comma operator in here, it'd eat the other arguments
+            arguments.push(assignment(iter)?);
+
+            if peek(iter).token != Token::COMMA {
+                break;
+            }
+            iter.next();
+        }
+    }
+
+    let paren = consume(iter, Token::RIGHT_PAREN, "Expect ')' after arguments.")?;
+    Ok(Expr::Call(Box::new(callee), paren, arguments))
+}
+
+fn primary(iter: &mut TokenIter<'_>) -> Result<Expr, ParseError> {
+    let expr = match &peek(iter).token {
+        Token::NUMBER(x) => Expr::Number(*x),
+        Token::STRING(str) => Expr::String(str.clone()),
+        Token::INTERPOLATED(_) => {
+            let Token::INTERPOLATED(segments) = iter.next().expect("We just peeked").token else {
+                unreachable!("We just peeked")
+            };
+            return Ok(Expr::Interpolation(
+                segments.into_iter().map(interpolated_segment).collect::<Result<_, _>>()?));
+        },
         Token::TRUE => Expr::Boolean(true),
         Token::FALSE => Expr::Boolean(false),
         Token::NIL => Expr::Nil,
-        Token::LEFT_PAREN => {
-            let expr = expression(iter);
-            match iter.next() {
-                Some(x) => {
-                    if let Token::RIGHT_PAREN = x {
-                        // good, just consume and move on
-                    }
-                    else{
-                        panic!("Expected closing parenthesis")
-                    }
+        Token::IDENTIFIER(_) => return Ok(Expr::Variable(iter.next().expect("We just peeked"), Depth::default())),
+        Token::THIS => return Ok(Expr::This(iter.next().expect("We just peeked"), Depth::default())),
+        Token::LEFT_BRACKET => {
+            iter.next();
+            let mut elements = Vec::new();
+            // a trailing comma is fine
+            while peek(iter).token != Token::RIGHT_BRACKET {
+                // no comma operator in here either
+                elements.push(assignment(iter)?);
+                if peek(iter).token != Token::COMMA {
+                    break;
                 }
-                None => panic!("Expected closing parenthesis")
-            };
+                iter.next();
+            }
+            consume(iter, Token::RIGHT_BRACKET, "Expect ']' after list elements.")?;
 
-            Expr::Grouping(Box::new(expr))
+            return Ok(Expr::List(elements));
         },
-        other => panic!("Bad token, {:?}", other)
-    }
+        Token::SUPER => {
+            let keyword = iter.next().expect("We just peeked");
+            consume(iter, Token::DOT, "Expect '.' after 'super'.")?;
+            let method = consume_identifier(iter, "Expect superclass method name.")?;
+
+            return Ok(Expr::Super(keyword, method, Depth::default()));
+        },
+        Token::LEFT_PAREN => {
+            iter.next();
+            let expr = expression(iter)?;
+            consume(iter, Token::RIGHT_PAREN, "Expect ')' after expression.")?;
+
+            return Ok(Expr::Grouping(Box::new(expr)));
+        },
+        Token::BANG_EQUAL | Token::EQUAL_EQUAL => return missing_left_operand(iter, comparison),
+        Token::GREATER | Token::GREATER_EQUAL |
+        Token::LESS | Token::LESS_EQUAL => return missing_left_operand(iter, term),
+        Token::PLUS => return missing_left_operand(iter, factor),
+        Token::SLASH | Token::STAR => return missing_left_operand(iter, unary),
+        Token::AND => return missing_left_operand(iter, equality),
+        Token::OR => return missing_left_operand(iter, logic_and),
+        Token::QUESTION => return missing_left_operand(iter, conditional),
+        Token::COMMA => return missing_left_operand(iter, assignment),
+        // don't consume it, synchronize might want it
+        _ => return error(peek(iter), "Expect expression.")
+    };
+
+    iter.next();
+    Ok(expr)
+}
+
+/**
+ * For e.g. `+ 3`. Still parses the right side, at the operator's own
+ * precedence, so the error doesn't set off a pile more after it
+ */
+fn missing_left_operand(iter: &mut TokenIter<'_>,
+                        right_operand: fn(&mut TokenIter<'_>) -> Result<Expr, ParseError>) -> Result<Expr, ParseError> {
+    let operator = iter.next().expect("We just peeked");
+    right_operand(iter)?;
+    error(&operator, "Missing left-hand operand.")
 }
 
+fn interpolated_segment(segment: StringSegment) -> Result<Expr, ParseError> {
+    match segment {
+        StringSegment::Text(str) => Ok(Expr::String(str)),
+        StringSegment::Code(tokens) => {
+            // the EOF the scanner ends the code with sits where the } is,
+            // so point errors there at the } instead
+            let at_brace = |error: ParseError| match error.token.token {
+                Token::EOF => ParseError { token: SpannedToken { token: Token::RIGHT_BRACE, ..error.token }, ..error },
+                _ => error
+            };
+            let mut iter = TokenIter::new(tokens.into_iter());
+            let expr = expression(&mut iter).map_err(at_brace)?;
 
-fn parse(token_list: &Vec<Token>) -> Expr {
-    let mut iter = token_list.iter().peekable();
-    
-    expression(&mut iter)
+            consume(&mut iter, Token::EOF, "Expect '}' after interpolated expression.").map_err(at_brace)?;
+            Ok(expr)
+        }
+    }
 }
+
 #[cfg(test)]
 mod tests {
-    use crate::{parser::parse, scanner::Token};
-    use crate::parser::Expr;
+    use crate::scanner::{self, Span, SpannedToken, StringSegment, Token};
+    use std::rc::Rc;
+
+    use crate::parser::{expression, program, Depth, Expr, Function, ParseError, Stmt, TokenIter};
+
+    fn parse(token_list: &[Token]) -> Expr {
+        let spanned: Vec<SpannedToken> = token_list.iter()
+            .map(|token| op(token.clone()))
+            .collect();
+        let mut iter = TokenIter::new(spanned.into_iter());
+
+        expression(&mut iter).expect("Should parse")
+    }
+
+    fn op(token: Token) -> SpannedToken {
+        SpannedToken { token, span: Span::default() }
+    }
+
+    fn name(name: &str) -> Token {
+        Token::IDENTIFIER(String::from(name))
+    }
+
     #[test]
     fn parse_tests() {
         // terminals
-        assert_eq!(parse(&vec![Token::TRUE, Token::EOF]), Expr::Boolean(true));
-        assert_eq!(parse(&vec![Token::FALSE, Token::EOF]), Expr::Boolean(false));
-        assert_eq!(parse(&vec![Token::NIL, Token::EOF]), Expr::Nil);
-        assert_eq!(parse(&vec![Token::STRING(String::from("blah")), 
+        assert_eq!(parse(&[Token::TRUE, Token::EOF]), Expr::Boolean(true));
+        assert_eq!(parse(&[Token::FALSE, Token::EOF]), Expr::Boolean(false));
+        assert_eq!(parse(&[Token::NIL, Token::EOF]), Expr::Nil);
+        assert_eq!(parse(&[Token::STRING(String::from("blah")), 
             Token::EOF]), Expr::String(String::from("blah")));
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::EOF]), Expr::Number(0.0));
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::EOF]), Expr::Number(0.0));
 
         // unary 
-        assert_eq!(parse(&vec![Token::BANG, Token::TRUE, Token::EOF]), 
-            Expr::Unary(Token::BANG, 
+        assert_eq!(parse(&[Token::BANG, Token::TRUE, Token::EOF]), 
+            Expr::Unary(op(Token::BANG), 
                 Box::new(Expr::Boolean(true))));
 
-        assert_eq!(parse(&vec![Token::MINUS, Token::NUMBER(0.0), Token::EOF]), 
-            Expr::Unary(Token::MINUS, 
+        assert_eq!(parse(&[Token::MINUS, Token::NUMBER(0.0), Token::EOF]), 
+            Expr::Unary(op(Token::MINUS), 
                 Box::new(Expr::Number(0.0))));
         
         // factor
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::STAR, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::STAR, 
                             Token::NUMBER(0.0), Token::SLASH,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::STAR,
+                                op(Token::STAR),
                                 Box::new(Expr::Number(0.0)))), 
-                Token::SLASH,
+                op(Token::SLASH),
                 Box::new(Expr::Number(0.0))));
 
 
-        assert_eq!(parse(&vec![Token::MINUS, Token::NUMBER(0.0), Token::STAR, 
+        assert_eq!(parse(&[Token::MINUS, Token::NUMBER(0.0), Token::STAR, 
                 Token::MINUS, Token::NUMBER(0.0),
                 Token::EOF]), 
         Expr::Binary(
             Box::new(
-                Expr::Unary(Token::MINUS,
+                Expr::Unary(op(Token::MINUS),
                     Box::new(Expr::Number(0.0)))), 
-            Token::STAR,
+            op(Token::STAR),
             Box::new(
-                Expr::Unary(Token::MINUS,
+                Expr::Unary(op(Token::MINUS),
                     Box::new(Expr::Number(0.0))))));
         
         // term
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::MINUS, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::MINUS, 
                             Token::NUMBER(0.0), Token::SLASH,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(Expr::Number(0.0)),
-                Token::MINUS,
+                op(Token::MINUS),
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::SLASH,
+                                op(Token::SLASH),
                                 Box::new(Expr::Number(0.0))))));
 
         
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::STAR, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::STAR, 
                             Token::NUMBER(0.0), Token::PLUS,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::STAR,
+                                op(Token::STAR),
                                 Box::new(Expr::Number(0.0)))),
-                Token::PLUS,
+                op(Token::PLUS),
                 Box::new(Expr::Number(0.0))));
 
         //comparison
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::GREATER, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::GREATER, 
                             Token::NUMBER(0.0), Token::PLUS,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(Expr::Number(0.0)),
-                Token::GREATER,
+                op(Token::GREATER),
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::PLUS,
+                                op(Token::PLUS),
                                 Box::new(Expr::Number(0.0))))));
 
         
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::MINUS, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::MINUS, 
                             Token::NUMBER(0.0), Token::LESS_EQUAL,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::MINUS,
+                                op(Token::MINUS),
                                 Box::new(Expr::Number(0.0)))),
-                Token::LESS_EQUAL,
+                op(Token::LESS_EQUAL),
                 Box::new(Expr::Number(0.0))));
         
 
         //equality
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::BANG_EQUAL, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::BANG_EQUAL, 
                             Token::NUMBER(0.0), Token::LESS,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(Expr::Number(0.0)),
-                Token::BANG_EQUAL,
+                op(Token::BANG_EQUAL),
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::LESS,
+                                op(Token::LESS),
                                 Box::new(Expr::Number(0.0))))));
 
         
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::GREATER_EQUAL, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::GREATER_EQUAL, 
                             Token::NUMBER(0.0), Token::EQUAL_EQUAL,
                             Token::NUMBER(0.0),
                             Token::EOF]), 
             Expr::Binary(
                 Box::new(
                     Expr::Binary(Box::new(Expr::Number(0.0)),
-                                Token::GREATER_EQUAL,
+                                op(Token::GREATER_EQUAL),
                                 Box::new(Expr::Number(0.0)))),
-                Token::EQUAL_EQUAL,
+                op(Token::EQUAL_EQUAL),
                 Box::new(Expr::Number(0.0))));
         
         // parenthesis
-        assert_eq!(parse(&vec![Token::NUMBER(0.0), Token::STAR, 
+        assert_eq!(parse(&[Token::NUMBER(0.0), Token::STAR, 
                             Token::LEFT_PAREN,
                             Token::NUMBER(0.0), Token::PLUS,
                             Token::NUMBER(0.0),
@@ -360,11 +1059,406 @@ mod tests {
                             Token::EOF]), 
 
             Expr::Binary(Box::new(Expr::Number(0.0)),
-                        Token::STAR,
+                        op(Token::STAR),
                         Box::new(Expr::Grouping(
                             Box::new(Expr::Binary(
                                 Box::new(Expr::Number(0.0)),
-                                Token::PLUS,
+                                op(Token::PLUS),
                                 Box::new(Expr::Number(0.0))))))));
     }
+
+    #[test]
+    fn parse_interpolation_tests() {
+        assert_eq!(parse(&[Token::INTERPOLATED(vec![
+                            StringSegment::Text(String::from("x = ")),
+                            StringSegment::Code(vec![op(Token::NUMBER(1.0)), op(Token::PLUS),
+                                                     op(Token::NUMBER(2.0)), op(Token::EOF)])]),
+                           Token::EOF]),
+            Expr::Interpolation(vec![
+                Expr::String(String::from("x = ")),
+                Expr::Binary(Box::new(Expr::Number(1.0)),
+                             op(Token::PLUS),
+                             Box::new(Expr::Number(2.0)))]));
+
+
+        // errors inside point at where they are in the source, not at the end
+        let errors = |source: &str| -> Vec<String> {
+            let tokens = scanner::scan_tokens(source).expect("Should scan");
+            program(tokens.into_iter()).unwrap_err().iter().map(|error| error.to_string()).collect()
+        };
+        assert_eq!(errors("print 1;\nprint \"a ${1 +} b\";"),
+            vec!["[line 2, column 15] Error at '}': Expect expression."]);
+        assert_eq!(errors("print \"${1 2}\";"),
+            vec!["[line 1, column 12] Error at '2': Expect '}' after interpolated expression."]);
+    }
+
+    fn parse_program(token_list: &[Token]) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let spanned: Vec<SpannedToken> = token_list.iter()
+            .map(|token| op(token.clone()))
+            .collect();
+
+        program(spanned.into_iter())
+    }
+
+    #[test]
+    fn parse_error_tests() {
+        assert_eq!(parse_program(&[Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::Print(Expr::Number(1.0))]));
+
+        assert_eq!(parse_program(&[Token::NUMBER(1.0), Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ';' after expression."), token: op(Token::EOF) }]));
+        assert_eq!(parse_program(&[Token::LEFT_PAREN, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ')' after expression."), token: op(Token::SEMICOLON) }]));
+
+        // recovers at the ; and at print, so all three get reported
+        let errors = parse_program(&[
+            Token::PLUS, Token::NUMBER(1.0), Token::SEMICOLON,
+            Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON,
+            Token::NUMBER(1.0), Token::STAR, Token::STAR, Token::NUMBER(2.0),
+            Token::PRINT, Token::NUMBER(2.0),
+            Token::EOF]).unwrap_err();
+        assert_eq!(errors, vec![
+            ParseError { message: String::from("Missing left-hand operand."), token: op(Token::PLUS) },
+            ParseError { message: String::from("Missing left-hand operand."), token: op(Token::STAR) },
+            ParseError { message: String::from("Expect ';' after value."), token: op(Token::EOF) },
+        ]);
+
+        assert_eq!(errors[0].to_string(), "[line 0, column 0] Error at '+': Missing left-hand operand.");
+        assert_eq!(errors[2].to_string(), "[line 0, column 0] Error at end: Expect ';' after value.");
+
+        // fun f() { var = 1; print 2; }, recovers inside the body so the } isn't an error
+        assert_eq!(parse_program(&[
+            Token::FUN, name("f"), Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::LEFT_BRACE,
+            Token::VAR, Token::EQUAL, Token::NUMBER(1.0), Token::SEMICOLON,
+            Token::PRINT, Token::NUMBER(2.0), Token::SEMICOLON,
+            Token::RIGHT_BRACE, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect variable name."), token: op(Token::EQUAL) }]));
+        // if (true) { print; print -; } print 2;
+        assert_eq!(parse_program(&[
+            Token::IF, Token::LEFT_PAREN, Token::TRUE, Token::RIGHT_PAREN, Token::LEFT_BRACE,
+            Token::PRINT, Token::SEMICOLON, Token::PRINT, Token::MINUS, Token::SEMICOLON, Token::RIGHT_BRACE,
+            Token::PRINT, Token::NUMBER(2.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![
+                ParseError { message: String::from("Expect expression."), token: op(Token::SEMICOLON) },
+                ParseError { message: String::from("Expect expression."), token: op(Token::SEMICOLON) },
+            ]));
+    }
+
+    #[test]
+    fn parse_var_tests() {
+        let x = || op(name("x"));
+        assert_eq!(parse_program(&[Token::VAR, name("x"), Token::EQUAL, Token::NUMBER(1.0), Token::SEMICOLON,
+                                   Token::VAR, name("x"), Token::SEMICOLON,
+                                   Token::PRINT, name("x"), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::Var(x(), Some(Expr::Number(1.0))),
+                    Stmt::Var(x(), None),
+                    Stmt::Print(Expr::Variable(x(), Depth::default()))]));
+
+        assert_eq!(parse_program(&[Token::VAR, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect variable name."), token: op(Token::NUMBER(1.0)) }]));
+        assert_eq!(parse_program(&[Token::VAR, name("x"), Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ';' after variable declaration."),
+                                  token: op(Token::NUMBER(1.0)) }]));
+    }
+
+    #[test]
+    fn parse_assignment_tests() {
+
+        // a = b = 1
+        assert_eq!(parse(&[name("a"), Token::EQUAL, name("b"), Token::EQUAL, Token::NUMBER(1.0), Token::EOF]),
+            Expr::Assign(op(name("a")),
+                Box::new(Expr::Assign(op(name("b")),
+                    Box::new(Expr::Number(1.0)),
+                    Depth::default())),
+                Depth::default()));
+
+        // 1 + 2 = 3
+        assert_eq!(parse_program(&[Token::NUMBER(1.0), Token::PLUS, Token::NUMBER(2.0), Token::EQUAL,
+                                   Token::NUMBER(3.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Invalid assignment target."), token: op(Token::EQUAL) }]));
+        // (a) = 3
+        assert_eq!(parse_program(&[Token::LEFT_PAREN, name("a"), Token::RIGHT_PAREN, Token::EQUAL,
+                                   Token::NUMBER(3.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Invalid assignment target."), token: op(Token::EQUAL) }]));
+    }
+
+    #[test]
+    fn parse_block_tests() {
+
+        // { var a; { print a; } }
+        assert_eq!(parse_program(&[Token::LEFT_BRACE, Token::VAR, name("a"), Token::SEMICOLON,
+                                   Token::LEFT_BRACE, Token::PRINT, name("a"), Token::SEMICOLON, Token::RIGHT_BRACE,
+                                   Token::RIGHT_BRACE, Token::EOF]),
+            Ok(vec![Stmt::Block(vec![
+                Stmt::Var(op(name("a")), None),
+                Stmt::Block(vec![Stmt::Print(Expr::Variable(op(name("a")), Depth::default()))])])]));
+
+        // { print 1;
+        assert_eq!(parse_program(&[Token::LEFT_BRACE, Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect '}' after block."), token: op(Token::EOF) }]));
+    }
+
+    #[test]
+    fn parse_control_flow_tests() {
+
+        // a or b and c
+        assert_eq!(parse(&[name("a"), Token::OR, name("b"), Token::AND, name("c"), Token::EOF]),
+            Expr::Logical(Box::new(Expr::Variable(op(name("a")), Depth::default())),
+                op(Token::OR),
+                Box::new(Expr::Logical(Box::new(Expr::Variable(op(name("b")), Depth::default())),
+                    op(Token::AND),
+                    Box::new(Expr::Variable(op(name("c")), Depth::default()))))));
+
+        // if (a) if (b) print 1; else print 2;
+        assert_eq!(parse_program(&[Token::IF, Token::LEFT_PAREN, name("a"), Token::RIGHT_PAREN,
+                                   Token::IF, Token::LEFT_PAREN, name("b"), Token::RIGHT_PAREN,
+                                   Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON,
+                                   Token::ELSE, Token::PRINT, Token::NUMBER(2.0), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::If(Expr::Variable(op(name("a")), Depth::default()),
+                Box::new(Stmt::If(Expr::Variable(op(name("b")), Depth::default()),
+                    Box::new(Stmt::Print(Expr::Number(1.0))),
+                    Some(Box::new(Stmt::Print(Expr::Number(2.0)))))),
+                None)]));
+
+        // for (var i = 0; i < 3; i = i + 1) print i;
+        let i = || Expr::Variable(op(name("i")), Depth::default());
+        assert_eq!(parse_program(&[Token::FOR, Token::LEFT_PAREN,
+                                   Token::VAR, name("i"), Token::EQUAL, Token::NUMBER(0.0), Token::SEMICOLON,
+                                   name("i"), Token::LESS, Token::NUMBER(3.0), Token::SEMICOLON,
+                                   name("i"), Token::EQUAL, name("i"), Token::PLUS, Token::NUMBER(1.0),
+                                   Token::RIGHT_PAREN, Token::PRINT, name("i"), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::Block(vec![
+                Stmt::Var(op(name("i")), Some(Expr::Number(0.0))),
+                Stmt::While(None, Expr::Binary(Box::new(i()), op(Token::LESS), Box::new(Expr::Number(3.0))),
+                    Box::new(Stmt::Print(i())),
+                    Some(Expr::Assign(op(name("i")),
+                        Box::new(Expr::Binary(Box::new(i()), op(Token::PLUS), Box::new(Expr::Number(1.0)))),
+                        Depth::default())))])]));
+
+        // for (;;) print 1;
+        assert_eq!(parse_program(&[Token::FOR, Token::LEFT_PAREN, Token::SEMICOLON, Token::SEMICOLON, Token::RIGHT_PAREN,
+                                   Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::While(None, Expr::Boolean(true), Box::new(Stmt::Print(Expr::Number(1.0))), None)]));
+
+        // while a print 1;
+        assert_eq!(parse_program(&[Token::WHILE, name("a"), Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect '(' after 'while'."), token: op(name("a")) }]));
+    }
+
+    #[test]
+    fn parse_function_tests() {
+
+        // f(1, 2)()
+        assert_eq!(parse(&[name("f"), Token::LEFT_PAREN, Token::NUMBER(1.0), Token::COMMA, Token::NUMBER(2.0),
+                           Token::RIGHT_PAREN, Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::EOF]),
+            Expr::Call(
+                Box::new(Expr::Call(Box::new(Expr::Variable(op(name("f")), Depth::default())),
+                    op(Token::RIGHT_PAREN),
+                    vec![Expr::Number(1.0), Expr::Number(2.0)])),
+                op(Token::RIGHT_PAREN),
+                vec![]));
+
+        // fun add(a, b) { return a + b; }
+        assert_eq!(parse_program(&[Token::FUN, name("add"), Token::LEFT_PAREN, name("a"), Token::COMMA, name("b"),
+                                   Token::RIGHT_PAREN, Token::LEFT_BRACE, Token::RETURN, name("a"), Token::PLUS, name("b"),
+                                   Token::SEMICOLON, Token::RIGHT_BRACE, Token::EOF]),
+            Ok(vec![Stmt::Function(Rc::new(Function {
+                name: op(name("add")),
+                params: vec![op(name("a")), op(name("b"))],
+                body: vec![Stmt::Return(op(Token::RETURN), Some(Expr::Binary(
+                    Box::new(Expr::Variable(op(name("a")), Depth::default())),
+                    op(Token::PLUS),
+                    Box::new(Expr::Variable(op(name("b")), Depth::default())))))],
+            }))]));
+
+        // fun f(1) {}
+        assert_eq!(parse_program(&[Token::FUN, name("f"), Token::LEFT_PAREN, Token::NUMBER(1.0), Token::RIGHT_PAREN,
+                                   Token::LEFT_BRACE, Token::RIGHT_BRACE, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect parameter name."), token: op(Token::NUMBER(1.0)) }]));
+
+        let mut too_many = vec![name("f"), Token::LEFT_PAREN];
+        for _ in 0..256 {
+            too_many.extend([Token::NUMBER(1.0), Token::COMMA]);
+        }
+        too_many.extend([Token::NUMBER(1.0), Token::RIGHT_PAREN, Token::SEMICOLON, Token::EOF]);
+        assert_eq!(parse_program(&too_many),
+            Err(vec![ParseError { message: String::from("Can't have more than 255 arguments."), token: op(Token::NUMBER(1.0)) }]));
+    }
+
+    #[test]
+    fn parse_class_tests() {
+
+        // class A { f() { this.x = 1; } }
+        assert_eq!(parse_program(&[Token::CLASS, name("A"), Token::LEFT_BRACE,
+                                   name("f"), Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::LEFT_BRACE,
+                                   Token::THIS, Token::DOT, name("x"), Token::EQUAL, Token::NUMBER(1.0), Token::SEMICOLON,
+                                   Token::RIGHT_BRACE, Token::RIGHT_BRACE, Token::EOF]),
+            Ok(vec![Stmt::Class(op(name("A")), None, vec![Rc::new(Function {
+                name: op(name("f")),
+                params: vec![],
+                body: vec![Stmt::Expression(Expr::Set(
+                    Box::new(Expr::This(op(Token::THIS), Depth::default())),
+                    op(name("x")),
+                    Box::new(Expr::Number(1.0))))],
+            })])]));
+
+        // a.b(1).c
+        assert_eq!(parse(&[name("a"), Token::DOT, name("b"), Token::LEFT_PAREN, Token::NUMBER(1.0), Token::RIGHT_PAREN,
+                           Token::DOT, name("c"), Token::EOF]),
+            Expr::Get(
+                Box::new(Expr::Call(
+                    Box::new(Expr::Get(Box::new(Expr::Variable(op(name("a")), Depth::default())), op(name("b")))),
+                    op(Token::RIGHT_PAREN),
+                    vec![Expr::Number(1.0)])),
+                op(name("c"))));
+
+        // class A { var x; f() {} }, skips just the bad member
+        assert_eq!(parse_program(&[Token::CLASS, name("A"), Token::LEFT_BRACE, Token::VAR, name("x"), Token::SEMICOLON,
+                                   name("f"), Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::LEFT_BRACE, Token::RIGHT_BRACE,
+                                   Token::RIGHT_BRACE, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect method name."), token: op(Token::VAR) }]));
+        // a.1
+        assert_eq!(parse_program(&[name("a"), Token::DOT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect property name after '.'."), token: op(Token::NUMBER(1.0)) }]));
+    }
+
+    #[test]
+    fn parse_inheritance_tests() {
+
+        // class B < A { f() { super.f(); } }
+        assert_eq!(parse_program(&[Token::CLASS, name("B"), Token::LESS, name("A"), Token::LEFT_BRACE,
+                                   name("f"), Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::LEFT_BRACE,
+                                   Token::SUPER, Token::DOT, name("f"), Token::LEFT_PAREN, Token::RIGHT_PAREN, Token::SEMICOLON,
+                                   Token::RIGHT_BRACE, Token::RIGHT_BRACE, Token::EOF]),
+            Ok(vec![Stmt::Class(op(name("B")), Some(Expr::Variable(op(name("A")), Depth::default())), vec![Rc::new(Function {
+                name: op(name("f")),
+                params: vec![],
+                body: vec![Stmt::Expression(Expr::Call(
+                    Box::new(Expr::Super(op(Token::SUPER), op(name("f")), Depth::default())),
+                    op(Token::RIGHT_PAREN),
+                    vec![]))],
+            })])]));
+
+        // class B < 1 {}
+        assert_eq!(parse_program(&[Token::CLASS, name("B"), Token::LESS, Token::NUMBER(1.0), Token::LEFT_BRACE,
+                                   Token::RIGHT_BRACE, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect superclass name."), token: op(Token::NUMBER(1.0)) }]));
+        // super;
+        assert_eq!(parse_program(&[Token::SUPER, Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect '.' after 'super'."), token: op(Token::SEMICOLON) }]));
+    }
+
+    #[test]
+    fn parse_jump_tests() {
+
+        // outer: while (true) { break outer; continue; }
+        assert_eq!(parse_program(&[name("outer"), Token::COLON, Token::WHILE, Token::LEFT_PAREN, Token::TRUE, Token::RIGHT_PAREN,
+                                   Token::LEFT_BRACE, Token::BREAK, name("outer"), Token::SEMICOLON,
+                                   Token::CONTINUE, Token::SEMICOLON, Token::RIGHT_BRACE, Token::EOF]),
+            Ok(vec![Stmt::While(Some(op(name("outer"))), Expr::Boolean(true),
+                Box::new(Stmt::Block(vec![
+                    Stmt::Break(op(Token::BREAK), Some(op(name("outer")))),
+                    Stmt::Continue(op(Token::CONTINUE), None)])),
+                None)]));
+
+        // a labelled expression statement is still just an expression
+        assert_eq!(parse_program(&[name("a"), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::Expression(Expr::Variable(op(name("a")), Depth::default()))]));
+
+        // outer: print 1;
+        assert_eq!(parse_program(&[name("outer"), Token::COLON, Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect loop after label."), token: op(Token::PRINT) }]));
+        // break 1;
+        assert_eq!(parse_program(&[Token::BREAK, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ';' after 'break'."), token: op(Token::NUMBER(1.0)) }]));
+        // a b break 1; continue 2;, recovers at break and continue
+        assert_eq!(parse_program(&[name("a"), name("b"), Token::BREAK, Token::NUMBER(1.0), Token::SEMICOLON,
+                                   Token::CONTINUE, Token::NUMBER(2.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![
+                ParseError { message: String::from("Expect ';' after expression."), token: op(name("b")) },
+                ParseError { message: String::from("Expect ';' after 'break'."), token: op(Token::NUMBER(1.0)) },
+                ParseError { message: String::from("Expect ';' after 'continue'."), token: op(Token::NUMBER(2.0)) },
+            ]));
+    }
+
+    #[test]
+    fn parse_conditional_and_comma_tests() {
+        let var = |n: &str| Box::new(Expr::Variable(op(name(n)), Depth::default()));
+
+        // a ? b : c ? d : e
+        assert_eq!(parse(&[name("a"), Token::QUESTION, name("b"), Token::COLON, name("c"),
+                           Token::QUESTION, name("d"), Token::COLON, name("e"), Token::EOF]),
+            Expr::Conditional(var("a"), var("b"), Box::new(Expr::Conditional(var("c"), var("d"), var("e")))));
+
+        // a == b ? c : d, binds looser than equality
+        assert_eq!(parse(&[name("a"), Token::EQUAL_EQUAL, name("b"), Token::QUESTION, name("c"), Token::COLON,
+                           name("d"), Token::EOF]),
+            Expr::Conditional(Box::new(Expr::Binary(var("a"), op(Token::EQUAL_EQUAL), var("b"))), var("c"), var("d")));
+
+        // a, b = 1, c
+        assert_eq!(parse(&[name("a"), Token::COMMA, name("b"), Token::EQUAL, Token::NUMBER(1.0), Token::COMMA,
+                           name("c"), Token::EOF]),
+            Expr::Comma(
+                Box::new(Expr::Comma(var("a"),
+                    Box::new(Expr::Assign(op(name("b")), Box::new(Expr::Number(1.0)), Depth::default())))),
+                var("c")));
+
+        // f((a, b), c) has two arguments
+        assert_eq!(parse(&[name("f"), Token::LEFT_PAREN, Token::LEFT_PAREN, name("a"), Token::COMMA, name("b"),
+                           Token::RIGHT_PAREN, Token::COMMA, name("c"), Token::RIGHT_PAREN, Token::EOF]),
+            Expr::Call(var("f"), op(Token::RIGHT_PAREN),
+                vec![Expr::Grouping(Box::new(Expr::Comma(var("a"), var("b")))), *var("c")]));
+
+        // a ? b;
+        assert_eq!(parse_program(&[name("a"), Token::QUESTION, name("b"), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ':' after then branch of conditional expression."),
+                                  token: op(Token::SEMICOLON) }]));
+    }
+
+    #[test]
+    fn parse_missing_left_operand_tests() {
+        // + 3 * 4; print 1;
+        assert_eq!(parse_program(&[Token::PLUS, Token::NUMBER(3.0), Token::STAR, Token::NUMBER(4.0), Token::SEMICOLON,
+                                   Token::PRINT, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Missing left-hand operand."), token: op(Token::PLUS) }]));
+
+        // == 1 == 2; only complains once, the right side is eaten at equality's precedence
+        assert_eq!(parse_program(&[Token::EQUAL_EQUAL, Token::NUMBER(1.0), Token::EQUAL_EQUAL, Token::NUMBER(2.0),
+                                   Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Missing left-hand operand."), token: op(Token::EQUAL_EQUAL) }]));
+
+        // - is fine, it's unary too
+        assert_eq!(parse_program(&[Token::MINUS, Token::NUMBER(3.0), Token::SEMICOLON, Token::EOF]),
+            Ok(vec![Stmt::Expression(Expr::Unary(op(Token::MINUS), Box::new(Expr::Number(3.0))))]));
+
+        let errors = parse_program(&[Token::AND, Token::TRUE, Token::SEMICOLON, Token::EOF]).unwrap_err();
+        assert_eq!(errors[0].to_string(), "[line 0, column 0] Error at 'and': Missing left-hand operand.");
+    }
+
+    #[test]
+    fn parse_list_tests() {
+        let var = |n: &str| Box::new(Expr::Variable(op(name(n)), Depth::default()));
+
+        // [1, [], 2,]
+        assert_eq!(parse(&[Token::LEFT_BRACKET, Token::NUMBER(1.0), Token::COMMA, Token::LEFT_BRACKET, Token::RIGHT_BRACKET,
+                           Token::COMMA, Token::NUMBER(2.0), Token::COMMA, Token::RIGHT_BRACKET, Token::EOF]),
+            Expr::List(vec![Expr::Number(1.0), Expr::List(vec![]), Expr::Number(2.0)]));
+
+        // xs[0][1] = 2
+        assert_eq!(parse(&[name("xs"), Token::LEFT_BRACKET, Token::NUMBER(0.0), Token::RIGHT_BRACKET,
+                           Token::LEFT_BRACKET, Token::NUMBER(1.0), Token::RIGHT_BRACKET, Token::EQUAL, Token::NUMBER(2.0), Token::EOF]),
+            Expr::SetIndex(
+                Box::new(Expr::Index(var("xs"), op(Token::RIGHT_BRACKET), Box::new(Expr::Number(0.0)))),
+                op(Token::RIGHT_BRACKET),
+                Box::new(Expr::Number(1.0)),
+                Box::new(Expr::Number(2.0))));
+
+        // [1 2]
+        assert_eq!(parse_program(&[Token::LEFT_BRACKET, Token::NUMBER(1.0), Token::NUMBER(2.0), Token::RIGHT_BRACKET,
+                                   Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ']' after list elements."), token: op(Token::NUMBER(2.0)) }]));
+        // xs[1;
+        assert_eq!(parse_program(&[name("xs"), Token::LEFT_BRACKET, Token::NUMBER(1.0), Token::SEMICOLON, Token::EOF]),
+            Err(vec![ParseError { message: String::from("Expect ']' after index."), token: op(Token::SEMICOLON) }]));
+    }
 }
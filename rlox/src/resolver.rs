@@ -0,0 +1,363 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::parser::{fmt_error_at, variable_name, Depth, Expr, Function, Stmt};
+use crate::scanner::SpannedToken;
+
+/**
+ * Mistake that's only visible once you know the scopes, e.g. reading a
+ * local in its own initializer
+ */
+#[derive(PartialEq, Debug, Clone)]
+pub struct ResolveError {
+    pub message: String,
+    pub token: SpannedToken,
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_error_at(f, &self.token, &self.message)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum FunctionType {
+    None,
+    Function,
+    Method,
+    Initializer,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+struct Resolver {
+    // one map per local scope, the bool is whether the variable's
+    // initializer has finished. Globals aren't tracked at all.
+    scopes: Vec<HashMap<String, bool>>,
+    current_function: FunctionType,
+    current_class: ClassType,
+    // labels of the loops we're inside, innermost last. A function body
+    // starts over with none.
+    loops: Vec<Option<String>>,
+    errors: Vec<ResolveError>,
+}
+
+/**
+ * Works out how many scopes out every local variable reference lives and
+ * stores it in the Depth slot of its Expr, so closures keep seeing the
+ * binding that was there when they were declared.
+ *
+ * Runs over everything even after an error so they all get reported.
+ */
+pub fn resolve(statements: &[Stmt]) -> Result<(), Vec<ResolveError>> {
+    let mut resolver = Resolver { scopes: Vec::new(), current_function: FunctionType::None,
+                                 current_class: ClassType::None, loops: Vec::new(), errors: Vec::new() };
+    resolver.statements(statements);
+
+    if !resolver.errors.is_empty() {
+        return Err(resolver.errors);
+    }
+    Ok(())
+}
+
+impl Resolver {
+    fn error(&mut self, token: &SpannedToken, message: &str) {
+        self.errors.push(ResolveError { message: message.to_string(), token: token.clone() });
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expr) | Stmt::Print(expr) => self.expression(expr),
+            Stmt::Var(name, initializer) => {
+                // declared but not defined yet, so the initializer can't see it
+                self.declare(name);
+                if let Some(initializer) = initializer {
+                    self.expression(initializer);
+                }
+                self.define(name);
+            }
+            Stmt::Block(statements) => {
+                self.scopes.push(HashMap::new());
+                self.statements(statements);
+                self.scopes.pop();
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::While(label, condition, body, increment) => {
+                self.expression(condition);
+                self.loops.push(label.as_ref().map(|label| variable_name(label).to_string()));
+                self.statement(body);
+                self.loops.pop();
+                if let Some(increment) = increment {
+                    self.expression(increment);
+                }
+            }
+            Stmt::Break(keyword, label) | Stmt::Continue(keyword, label) => self.jump(keyword, label),
+            Stmt::Function(function) => {
+                // defined straight away so the function can call itself
+                self.declare(&function.name);
+                self.define(&function.name);
+                self.function(function, FunctionType::Function);
+            }
+            Stmt::Class(name, superclass, methods) => {
+                let enclosing_class = self.current_class;
+                self.current_class = ClassType::Class;
+                self.declare(name);
+                self.define(name);
+
+                // same shape as the interpreter, a scope with super in it
+                // wrapped around the one with this
+                if let Some(superclass) = superclass {
+                    if let Expr::Variable(superclass_name, _) = superclass {
+                        if variable_name(superclass_name) == variable_name(name) {
+                            self.error(superclass_name, "A class can't inherit from itself.");
+                        }
+                    }
+                    self.current_class = ClassType::Subclass;
+                    self.expression(superclass);
+                    self.scopes.push(HashMap::from([(String::from("super"), true)]));
+                }
+
+                // methods get bound in a scope of their own with just this in it
+                self.scopes.push(HashMap::from([(String::from("this"), true)]));
+                for method in methods {
+                    let function_type = match variable_name(&method.name) {
+                        "init" => FunctionType::Initializer,
+                        _ => FunctionType::Method
+                    };
+                    self.function(method, function_type);
+                }
+                self.scopes.pop();
+
+                if superclass.is_some() {
+                    self.scopes.pop();
+                }
+                self.current_class = enclosing_class;
+            }
+            Stmt::Return(keyword, value) => {
+                if self.current_function == FunctionType::None {
+                    self.error(keyword, "Can't return from top-level code.");
+                }
+                if let Some(value) = value {
+                    if self.current_function == FunctionType::Initializer {
+                        self.error(keyword, "Can't return a value from an initializer.");
+                    }
+                    self.expression(value);
+                }
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Function, function_type: FunctionType) {
+        let enclosing_function = self.current_function;
+        self.current_function = function_type;
+        let enclosing_loops = std::mem::take(&mut self.loops);
+
+        // params and body share a scope, same as the environment a call makes
+        self.scopes.push(HashMap::new());
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        self.statements(&function.body);
+        self.scopes.pop();
+
+        self.current_function = enclosing_function;
+        self.loops = enclosing_loops;
+    }
+
+    fn jump(&mut self, keyword: &SpannedToken, label: &Option<SpannedToken>) {
+        match label {
+            _ if self.loops.is_empty() =>
+                self.error(keyword, &format!("Can't use '{}' outside of a loop.", keyword.token)),
+            Some(label) => {
+                let name = variable_name(label);
+                if !self.loops.iter().any(|loop_label| loop_label.as_deref() == Some(name)) {
+                    self.error(label, &format!("No enclosing loop labeled '{}'.", name));
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Variable(name, depth) => {
+                let in_initializer = self.scopes.last()
+                    .and_then(|scope| scope.get(variable_name(name)))
+                    .is_some_and(|defined| !defined);
+                if in_initializer {
+                    self.error(name, "Can't read local variable in its own initializer.");
+                }
+                self.local(variable_name(name), depth);
+            }
+            Expr::Assign(name, value, depth) => {
+                self.expression(value);
+                self.local(variable_name(name), depth);
+            }
+            Expr::This(keyword, depth) => {
+                if self.current_class == ClassType::None {
+                    self.error(keyword, "Can't use 'this' outside of a class.");
+                }
+                self.local("this", depth);
+            }
+            Expr::Super(keyword, _, depth) => {
+                match self.current_class {
+                    ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+                    ClassType::Class => self.error(keyword, "Can't use 'super' in a class with no superclass."),
+                    ClassType::Subclass => {}
+                }
+                self.local("super", depth);
+            }
+            Expr::Get(object, _) => self.expression(object),
+            Expr::Set(object, _, value) => {
+                self.expression(value);
+                self.expression(object);
+            }
+            Expr::Conditional(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) | Expr::Comma(left, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Grouping(expr) | Expr::Unary(_, expr) => self.expression(expr),
+            Expr::Call(callee, _, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Index(list, _, index) => {
+                self.expression(list);
+                self.expression(index);
+            }
+            Expr::SetIndex(list, _, index, value) => {
+                self.expression(value);
+                self.expression(list);
+                self.expression(index);
+            }
+            Expr::List(parts) | Expr::Interpolation(parts) => {
+                for part in parts {
+                    self.expression(part);
+                }
+            }
+            Expr::String(_) | Expr::Number(_) | Expr::Boolean(_) | Expr::Nil => {}
+        }
+    }
+
+    fn declare(&mut self, name: &SpannedToken) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        if scope.insert(variable_name(name).to_string(), false).is_some() {
+            self.error(name, "Already a variable with this name in this scope.");
+        }
+    }
+
+    fn define(&mut self, name: &SpannedToken) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(variable_name(name).to_string(), true);
+        }
+    }
+
+    /**
+     * Leaves the depth alone if it's not in any local scope, then it's a global
+     */
+    fn local(&mut self, name: &str, depth: &Depth) {
+        let distance = self.scopes.iter().rev()
+            .position(|scope| scope.contains_key(name));
+        depth.set(distance);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{self, Expr, Stmt};
+    use crate::resolver::{resolve, ResolveError};
+    use crate::scanner;
+
+    fn resolved(source: &str) -> Result<Vec<Stmt>, Vec<ResolveError>> {
+        let tokens = scanner::scan_tokens(source).expect("Should scan");
+        let statements = parser::program(tokens.into_iter()).expect("Should parse");
+        resolve(&statements).map(|_| statements)
+    }
+
+    fn messages(source: &str) -> Vec<String> {
+        resolved(source).unwrap_err().into_iter().map(|error| error.message).collect()
+    }
+
+    #[test]
+    fn depth_tests() {
+        // { var a; { fun f() { print a; } } } print a;
+        let statements = resolved("{ var a; { fun f() { print a; } } } print a;").unwrap();
+
+        let Stmt::Block(outer) = &statements[0] else { panic!("Expected block") };
+        let Stmt::Block(inner) = &outer[1] else { panic!("Expected block") };
+        let Stmt::Function(function) = &inner[0] else { panic!("Expected function") };
+        let Stmt::Print(Expr::Variable(_, depth)) = &function.body[0] else { panic!("Expected print") };
+        // function scope, inner block, then the block with a in it
+        assert_eq!(depth.get(), Some(2));
+
+        let Stmt::Print(Expr::Variable(_, depth)) = &statements[1] else { panic!("Expected print") };
+        assert_eq!(depth.get(), None);
+    }
+
+    #[test]
+    fn resolve_error_tests() {
+        assert_eq!(messages("{ var a = 1; { var a = a; } }"),
+            vec!["Can't read local variable in its own initializer."]);
+        // globals are allowed to, jlox does the same
+        assert!(resolved("var a = 1; var a = a;").is_ok());
+
+        assert_eq!(messages("return 1;"), vec!["Can't return from top-level code."]);
+        assert!(resolved("fun f() { { return 1; } }").is_ok());
+
+        assert_eq!(messages("fun f(a, a) {} { var b; var b; } return;"),
+            vec!["Already a variable with this name in this scope.",
+                 "Already a variable with this name in this scope.",
+                 "Can't return from top-level code."]);
+
+        assert_eq!(messages("print this; fun f() { return this; } class A { init() { return 1; } m() { return this; } }"),
+            vec!["Can't use 'this' outside of a class.",
+                 "Can't use 'this' outside of a class.",
+                 "Can't return a value from an initializer."]);
+        // a bare return in init is fine, it hands back this
+        assert!(resolved("class A { init() { return; } }").is_ok());
+
+        assert_eq!(messages("class A < A {} print super.x; class B { f() { super.f(); } }"),
+            vec!["A class can't inherit from itself.",
+                 "Can't use 'super' outside of a class.",
+                 "Can't use 'super' in a class with no superclass."]);
+        assert!(resolved("class A {} class B < A { f() { return super.f; } }").is_ok());
+
+        assert_eq!(messages("break; { continue; } while (true) { fun f() { break; } } a: while (true) { break b; }"),
+            vec!["Can't use 'break' outside of a loop.",
+                 "Can't use 'continue' outside of a loop.",
+                 "Can't use 'break' outside of a loop.",
+                 "No enclosing loop labeled 'b'."]);
+        assert!(resolved("a: for (;;) { while (true) { continue a; } break; }").is_ok());
+
+        let errors = resolved("fun f() {}\nreturn;").unwrap_err();
+        assert_eq!(errors[0].to_string(), "[line 2, column 1] Error at 'return': Can't return from top-level code.");
+    }
+}
@@ -1,11 +1,18 @@
-use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::BufRead;
+use std::iter::Peekable;
+use std::str::CharIndices;
 
-#[allow(non_camel_case_types)]
+use unicode_normalization::UnicodeNormalization;
+use unicode_xid::UnicodeXID;
+
+#[allow(non_camel_case_types, clippy::upper_case_acronyms)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum Token{
     // Single-character tokens.
-    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE,
-    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR,
+    LEFT_PAREN, RIGHT_PAREN, LEFT_BRACE, RIGHT_BRACE, LEFT_BRACKET, RIGHT_BRACKET,
+    COMMA, DOT, MINUS, PLUS, SEMICOLON, SLASH, STAR, COLON, QUESTION,
 
     // One or two character tokens.
     BANG, BANG_EQUAL,
@@ -15,15 +22,283 @@ pub enum Token{
 
     // Literals.
     IDENTIFIER(String), STRING(String), NUMBER(f64), 
+    INTERPOLATED(Vec<StringSegment>),
 
     // Keywords.
-    AND, CLASS, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
+    AND, BREAK, CLASS, CONTINUE, ELSE, FALSE, FUN, FOR, IF, NIL, OR,
     PRINT, RETURN, SUPER, THIS, TRUE, VAR, WHILE,
 
+    // Trivia, only produced by Scanner::lossless.
+    WHITESPACE(String), NEWLINE, COMMENT(String),
+
     EOF
 }
 
-fn look_ahead_1_char(iter: &mut std::iter::Peekable<std::str::Chars<'_>>, char_to_match: char, if_match: Token, else_match: Token) -> Token {
+impl Token {
+    /**
+     * Just the variant name, e.g. "NUMBER" for NUMBER(1.0)
+     */
+    pub fn kind_name(&self) -> &'static str {
+        match self {
+            Token::LEFT_PAREN => "LEFT_PAREN",
+            Token::RIGHT_PAREN => "RIGHT_PAREN",
+            Token::LEFT_BRACE => "LEFT_BRACE",
+            Token::RIGHT_BRACE => "RIGHT_BRACE",
+            Token::LEFT_BRACKET => "LEFT_BRACKET",
+            Token::RIGHT_BRACKET => "RIGHT_BRACKET",
+            Token::COMMA => "COMMA",
+            Token::DOT => "DOT",
+            Token::MINUS => "MINUS",
+            Token::PLUS => "PLUS",
+            Token::SEMICOLON => "SEMICOLON",
+            Token::SLASH => "SLASH",
+            Token::STAR => "STAR",
+            Token::COLON => "COLON",
+            Token::QUESTION => "QUESTION",
+            Token::BANG => "BANG",
+            Token::BANG_EQUAL => "BANG_EQUAL",
+            Token::EQUAL => "EQUAL",
+            Token::EQUAL_EQUAL => "EQUAL_EQUAL",
+            Token::GREATER => "GREATER",
+            Token::GREATER_EQUAL => "GREATER_EQUAL",
+            Token::LESS => "LESS",
+            Token::LESS_EQUAL => "LESS_EQUAL",
+            Token::IDENTIFIER(_) => "IDENTIFIER",
+            Token::STRING(_) => "STRING",
+            Token::NUMBER(_) => "NUMBER",
+            Token::INTERPOLATED(_) => "INTERPOLATED",
+            Token::AND => "AND",
+            Token::BREAK => "BREAK",
+            Token::CLASS => "CLASS",
+            Token::CONTINUE => "CONTINUE",
+            Token::ELSE => "ELSE",
+            Token::FALSE => "FALSE",
+            Token::FUN => "FUN",
+            Token::FOR => "FOR",
+            Token::IF => "IF",
+            Token::NIL => "NIL",
+            Token::OR => "OR",
+            Token::PRINT => "PRINT",
+            Token::RETURN => "RETURN",
+            Token::SUPER => "SUPER",
+            Token::THIS => "THIS",
+            Token::TRUE => "TRUE",
+            Token::VAR => "VAR",
+            Token::WHILE => "WHILE",
+            Token::WHITESPACE(_) => "WHITESPACE",
+            Token::NEWLINE => "NEWLINE",
+            Token::COMMENT(_) => "COMMENT",
+            Token::EOF => "EOF",
+        }
+    }
+}
+
+/**
+ * Roughly how the token looks in source, for error messages
+ */
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            Token::IDENTIFIER(name) => return write!(f, "{}", name),
+            Token::STRING(str) => return write!(f, "{:?}", str),
+            Token::NUMBER(x) => return write!(f, "{}", x),
+            Token::INTERPOLATED(_) => "interpolated string",
+            Token::LEFT_PAREN => "(",
+            Token::RIGHT_PAREN => ")",
+            Token::LEFT_BRACE => "{",
+            Token::RIGHT_BRACE => "}",
+            Token::LEFT_BRACKET => "[",
+            Token::RIGHT_BRACKET => "]",
+            Token::COMMA => ",",
+            Token::DOT => ".",
+            Token::MINUS => "-",
+            Token::PLUS => "+",
+            Token::SEMICOLON => ";",
+            Token::SLASH => "/",
+            Token::STAR => "*",
+            Token::COLON => ":",
+            Token::QUESTION => "?",
+            Token::BANG => "!",
+            Token::BANG_EQUAL => "!=",
+            Token::EQUAL => "=",
+            Token::EQUAL_EQUAL => "==",
+            Token::GREATER => ">",
+            Token::GREATER_EQUAL => ">=",
+            Token::LESS => "<",
+            Token::LESS_EQUAL => "<=",
+            Token::EOF => "end",
+            // keywords and trivia
+            other => return write!(f, "{}", other.kind_name().to_lowercase())
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+/**
+ * Piece of a string literal with ${ } in it
+ */
+#[derive(PartialEq, Debug, Clone)]
+pub enum StringSegment {
+    Text(String),
+    // tokens between the ${ and }, with an EOF at the }
+    Code(Vec<SpannedToken>),
+}
+
+/**
+ * Where a token sits in the source. `offset` and `len` are in bytes,
+ * `line` and `column` are 1-based and count chars.
+ */
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.column)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub enum ScanErrorKind {
+    UnexpectedCharacter(char),
+    UnterminatedString,
+    InvalidEscape(String),
+    UnterminatedBlockComment,
+    MalformedNumber(String),
+    NumberTooLarge(String),
+    ReadFailed(String),
+    MalformedRawString,
+}
+
+#[derive(PartialEq, Debug, Clone)]
+pub struct ScanError {
+    pub kind: ScanErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] Error: ", self.span)?;
+        match &self.kind {
+            ScanErrorKind::UnexpectedCharacter(c) => write!(f, "Unexpected character {:?}.", c),
+            ScanErrorKind::UnterminatedString => write!(f, "Unterminated string."),
+            ScanErrorKind::InvalidEscape(sequence) => write!(f, "Invalid escape sequence '{}'.", sequence),
+            ScanErrorKind::UnterminatedBlockComment => write!(f, "Unterminated block comment."),
+            ScanErrorKind::MalformedNumber(lexeme) => write!(f, "Malformed number literal '{}'.", lexeme),
+            ScanErrorKind::NumberTooLarge(lexeme) => write!(f, "Number literal '{}' is too large.", lexeme),
+            ScanErrorKind::ReadFailed(reason) => write!(f, "Could not read source: {}", reason),
+            ScanErrorKind::MalformedRawString => write!(f, "Expected '\"' to start raw string."),
+        }
+    }
+}
+
+impl ScanError {
+    /**
+     * Whether it's just the source stopping part way through a token, so
+     * more source might fix it
+     */
+    pub fn is_unfinished(&self) -> bool {
+        matches!(self.kind, ScanErrorKind::UnterminatedString | ScanErrorKind::UnterminatedBlockComment)
+    }
+}
+
+/**
+ * Char iterator over the source that keeps track of where it is
+ */
+pub struct SourceIter<'a> {
+    chars: Peekable<CharIndices<'a>>,
+    source: &'a str,
+    // byte offset `chars` started at
+    base: usize,
+    line: usize,
+    column: usize,
+}
+
+impl<'a> SourceIter<'a> {
+    pub fn new(source: &'a str) -> Self {
+        SourceIter::starting_at(source, Span { offset: 0, line: 1, column: 1, len: 0 })
+    }
+
+    /**
+     * Picks up scanning part way through the source, at `position`
+     */
+    pub fn starting_at(source: &'a str, position: Span) -> Self {
+        SourceIter {
+            chars: source[position.offset..].char_indices().peekable(),
+            source,
+            base: position.offset,
+            line: position.line,
+            column: position.column
+        }
+    }
+
+    pub fn peek(&mut self) -> Option<&char> {
+        self.chars.peek().map(|(_, c)| c)
+    }
+
+    /**
+     * Byte offset of the next char, or the source length at end of file
+     */
+    pub fn offset(&mut self) -> usize {
+        let len = self.source.len();
+        self.chars.peek().map_or(len, |(i, _)| self.base + *i)
+    }
+
+    /**
+     * Span of length 0 at the next char, to be finished by `span_from`
+     */
+    pub fn position(&mut self) -> Span {
+        Span { offset: self.offset(), line: self.line, column: self.column, len: 0 }
+    }
+
+    pub fn span_from(&mut self, start: Span) -> Span {
+        Span { len: self.offset() - start.offset, ..start }
+    }
+
+    /**
+     * The source text from `start` up to the next char
+     */
+    pub fn slice_from(&mut self, start: Span) -> &'a str {
+        let end = self.offset();
+        &self.source[start.offset..end]
+    }
+
+    /**
+     * The char after the one `peek` would return
+     */
+    pub fn peek_next(&mut self) -> Option<char> {
+        let offset = self.offset();
+        self.source[offset..].chars().nth(1)
+    }
+}
+
+impl Iterator for SourceIter<'_> {
+    type Item = char;
+
+    fn next(&mut self) -> Option<char> {
+        let (_, c) = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        }
+        else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+}
+
+fn look_ahead_1_char(iter: &mut SourceIter<'_>, char_to_match: char, if_match: Token, else_match: Token) -> Token {
     match iter.peek() {
         Some(char) => {
             if *char == char_to_match{
@@ -39,38 +314,342 @@ fn look_ahead_1_char(iter: &mut std::iter::Peekable<std::str::Chars<'_>>, char_t
     }
 }
 /**
- * Returns None if end of file and no closing "
+ * Consumes the rest of an escape sequence after the \, returns None if it's
+ * not a valid one. Never consumes a " that isn't part of the sequence.
+ */
+fn get_escaped_char(iter: &mut SourceIter<'_>) -> Option<char> {
+    match iter.peek()? {
+        'n' => { iter.next(); Some('\n') },
+        't' => { iter.next(); Some('\t') },
+        'r' => { iter.next(); Some('\r') },
+        '0' => { iter.next(); Some('\0') },
+        '\\' => { iter.next(); Some('\\') },
+        '"' => { iter.next(); Some('"') },
+        '$' => { iter.next(); Some('$') },
+        'u' => {
+            iter.next();
+            if iter.peek() != Some(&'{') {
+                return None;
+            }
+            iter.next();
+
+            let mut hex_digits = String::new();
+            while let Some(next) = iter.peek() {
+                if !next.is_ascii_hexdigit() {
+                    break;
+                }
+                hex_digits.push(iter.next().expect("We just peeked it"));
+            }
+
+            if iter.peek() != Some(&'}') {
+                return None;
+            }
+            iter.next();
+
+            if hex_digits.is_empty() || hex_digits.len() > 6 {
+                return None;
+            }
+
+            // can still be a surrogate or past 10FFFF
+            char::from_u32(u32::from_str_radix(&hex_digits, 16).ok()?)
+        },
+        _ => {
+            // skip the bad char so the error covers it
+            iter.next();
+            None
+        }
+    }
+}
+
+/**
+ * Scans the tokens of an interpolated expression after its ${, up to and
+ * including the matching }. Errors inside don't stop it, the first one is
+ * returned once it's found the end.
  */
-fn get_string_literal_token(iter: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<Token> {
+fn get_interpolated_code(iter: &mut SourceIter<'_>, start: Span) -> Result<Vec<SpannedToken>, ScanError> {
+    let mut tokens = Vec::new();
+    let mut first_error = None;
+    let mut depth = 0;
+
+    loop {
+        let next = match scan_single_token(iter, false) {
+            Some(Ok(next)) => next,
+            Some(Err(error)) => {
+                first_error.get_or_insert(error);
+                continue;
+            },
+            None => return Err(ScanError {
+                kind: ScanErrorKind::UnterminatedString,
+                span: iter.span_from(start)
+            })
+        };
+
+        match next.token {
+            Token::LEFT_BRACE => depth += 1,
+            Token::RIGHT_BRACE if depth == 0 => {
+                tokens.push(SpannedToken { token: Token::EOF, span: next.span });
+                break;
+            },
+            Token::RIGHT_BRACE => depth -= 1,
+            _ => {}
+        }
+        tokens.push(next);
+    }
+
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(tokens)
+    }
+}
+
+/**
+ * Scans the rest of a string literal after the opening ", which is at `start`.
+ * On a bad escape it keeps going to the closing " and reports the first one.
+ * Gives back an INTERPOLATED token instead of a STRING if it has any ${ }.
+ */
+fn get_string_literal_token(iter: &mut SourceIter<'_>, start: Span) -> Result<Token, ScanError> {
     let mut string_lit = String::new();
+    let mut segments = Vec::new();
+    let mut first_error = None;
 
-    while let Some(next) = iter.next() {
-        // TODO: line increments
-        if next == '"' { // done with string literal
-            return Some(Token::STRING(string_lit))
+    loop {
+        let char_start = iter.position();
+        let next = match iter.next() {
+            Some(next) => next,
+            None => return Err(ScanError {
+                kind: ScanErrorKind::UnterminatedString,
+                span: iter.span_from(start)
+            })
+        };
+
+        match next {
+            '"' => break, // done with string literal
+            '$' if iter.peek() == Some(&'{') => {
+                iter.next();
+                if !string_lit.is_empty() {
+                    segments.push(StringSegment::Text(std::mem::take(&mut string_lit)));
+                }
+                match get_interpolated_code(iter, start) {
+                    Ok(code) => segments.push(StringSegment::Code(code)),
+                    Err(error) => { first_error.get_or_insert(error); }
+                }
+            },
+            '\\' => match get_escaped_char(iter) {
+                Some(escaped) => string_lit.push(escaped),
+                None => {
+                    let sequence = iter.slice_from(char_start).to_string();
+                    first_error.get_or_insert(ScanError {
+                        kind: ScanErrorKind::InvalidEscape(sequence),
+                        span: iter.span_from(char_start)
+                    });
+                }
+            },
+            other => string_lit.push(other)
         }
-        string_lit.push(next);
     }
 
-    return None
+    if let Some(error) = first_error {
+        return Err(error);
+    }
+    if segments.is_empty() {
+        return Ok(Token::STRING(string_lit));
+    }
+    if !string_lit.is_empty() {
+        segments.push(StringSegment::Text(string_lit));
+    }
+    Ok(Token::INTERPOLATED(segments))
 }
 
-fn is_digit(c: char) -> bool {
-    match c {
-        '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9' => true,
-        _ => false
+/**
+ * Scans the rest of a raw string after the r, e.g. r"C:\dir" or r#"say "hi""#.
+ * No escapes or interpolation, it ends at the first " followed by as many #
+ * as it started with.
+ */
+fn get_raw_string_token(iter: &mut SourceIter<'_>, start: Span) -> Result<Token, ScanError> {
+    let mut hashes = 0;
+    while iter.peek() == Some(&'#') {
+        iter.next();
+        hashes += 1;
+    }
+
+    if iter.next() != Some('"') {
+        return Err(ScanError {
+            kind: ScanErrorKind::MalformedRawString,
+            span: iter.span_from(start)
+        });
+    }
+
+    let mut string_lit = String::new();
+    loop {
+        let next = match iter.next() {
+            Some(next) => next,
+            None => return Err(ScanError {
+                kind: ScanErrorKind::UnterminatedString,
+                span: iter.span_from(start)
+            })
+        };
+
+        if next == '"' {
+            let mut closing_hashes = 0;
+            while closing_hashes < hashes && iter.peek() == Some(&'#') {
+                iter.next();
+                closing_hashes += 1;
+            }
+            if closing_hashes == hashes {
+                return Ok(Token::STRING(string_lit));
+            }
+
+            // not the end after all
+            string_lit.push('"');
+            string_lit.push_str(&"#".repeat(closing_hashes));
+        }
+        else {
+            string_lit.push(next);
+        }
     }
 }
 
+fn leading_whitespace(line: &str) -> usize {
+    line.len() - line.trim_start_matches([' ', '\t']).len()
+}
+
 /**
- * Returns biggest numeric string it can get from the iter
+ * Scans the rest of a """ string after the first ", which is at `start`.
+ * Escapes work like in normal strings, but there's no interpolation.
+ *
+ * The indentation common to every line gets stripped, and so does the
+ * line break after the opening """ and before the closing one if there's
+ * nothing else on those lines, so that
+ *     """
+ *     a
+ *       b
+ *     """
+ * is "a\n  b".
  */
-fn get_numeric_string(iter: &mut std::iter::Peekable<std::str::Chars<'_>>) -> String {
+fn get_triple_quoted_string_token(iter: &mut SourceIter<'_>, start: Span) -> Result<Token, ScanError> {
+    iter.next();
+    iter.next();
+    let content_start = iter.position();
+
+    // find the end first, since the indentation depends on every line
+    let content_end = loop {
+        let before = iter.offset();
+        match iter.next() {
+            Some('"') if iter.peek() == Some(&'"') && iter.peek_next() == Some('"') => {
+                iter.next();
+                iter.next();
+                break before;
+            },
+            // so \" doesn't end it
+            Some('\\') => { iter.next(); },
+            Some(_) => {},
+            None => return Err(ScanError {
+                kind: ScanErrorKind::UnterminatedString,
+                span: iter.span_from(start)
+            })
+        }
+    };
+
+    let contents = &iter.source[content_start.offset..content_end];
+    let lines: Vec<&str> = contents.split('\n').collect();
+    // the \r of a \r\n line break is left on the end of each line
+    let is_blank = |line: &str| line.trim_start_matches([' ', '\t', '\r']).is_empty();
+
+    let skip_first = lines.len() > 1 && is_blank(lines[0]);
+    let skip_last = lines.len() > 1 && is_blank(lines[lines.len() - 1]);
+    let kept = (skip_first as usize)..(lines.len() - skip_last as usize);
+
+    // a first line with text on it sits right after the """, so its
+    // indentation doesn't count
+    let indent = lines[kept.clone()].iter().enumerate()
+        .filter(|(i, line)| (*i > 0 || skip_first) && !is_blank(line))
+        .map(|(_, line)| leading_whitespace(line))
+        .min()
+        .unwrap_or(0);
+
+    let mut result = Vec::new();
+    let mut first_error = None;
+    let mut line_start = content_start.offset;
+    for (i, line) in lines.iter().enumerate() {
+        if kept.contains(&i) {
+            let dedent = if i == 0 { 0 } else { indent.min(leading_whitespace(line)) };
+            let position = if i == 0 {
+                content_start
+            } else {
+                // the indentation is all ascii so bytes are chars here
+                Span { offset: line_start + dedent, line: content_start.line + i, column: 1 + dedent, len: 0 }
+            };
+
+            let mut line_iter = SourceIter::starting_at(iter.source, position);
+            let mut text = String::new();
+            let line_end = line_start + line.strip_suffix('\r').unwrap_or(line).len();
+            while line_iter.offset() < line_end {
+                let char_start = line_iter.position();
+                match line_iter.next().expect("Still inside the string") {
+                    '\\' => match get_escaped_char(&mut line_iter) {
+                        Some(escaped) => text.push(escaped),
+                        None => {
+                            first_error.get_or_insert(ScanError {
+                                kind: ScanErrorKind::InvalidEscape(line_iter.slice_from(char_start).to_string()),
+                                span: line_iter.span_from(char_start)
+                            });
+                        }
+                    },
+                    other => text.push(other)
+                }
+            }
+            result.push(text);
+        }
+        line_start += line.len() + 1;
+    }
+
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(Token::STRING(result.join("\n")))
+    }
+}
+
+/**
+ * Skips the rest of a block comment after its opening slash-star,
+ * including any nested ones. Returns false if end of file comes first.
+ */
+fn skip_block_comment(iter: &mut SourceIter<'_>) -> bool {
+    let mut depth = 1;
+
+    while let Some(current) = iter.next() {
+        match (current, iter.peek()) {
+            ('/', Some('*')) => {
+                iter.next();
+                depth += 1;
+            },
+            ('*', Some('/')) => {
+                iter.next();
+                depth -= 1;
+                if depth == 0 {
+                    return true;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    false
+}
+
+fn is_digit(c: char) -> bool {
+    matches!(c, '0' | '1' | '2' | '3' | '4' | '5' | '6' | '7' | '8' | '9')
+}
+
+/**
+ * Returns biggest numeric string it can get from the iter, counting `_`
+ * separators as part of it
+ */
+fn get_numeric_string(iter: &mut SourceIter<'_>, is_numeric: fn(char) -> bool) -> String {
     let mut numeric_literal = String::new();
 
     while let Some(next) = iter.peek() {
         let next_char = *next;
-        if is_digit(next_char){
+        if is_numeric(next_char) || next_char == '_' {
             numeric_literal.push(next_char);
             iter.next();
         }
@@ -82,39 +661,106 @@ fn get_numeric_string(iter: &mut std::iter::Peekable<std::str::Chars<'_>>) -> St
     numeric_literal
 }
 
-fn get_numeric_literal(iter: &mut std::iter::Peekable<std::str::Chars<'_>>, first_char: char) -> Token {
+/**
+ * `_` is only allowed between two digits
+ */
+fn has_valid_separators(digits: &str) -> bool {
+    !digits.is_empty() && !digits.starts_with('_') && !digits.ends_with('_') && !digits.contains("__")
+}
+
+fn get_numeric_literal(iter: &mut SourceIter<'_>, first_char: char, start: Span) -> Result<Token, ScanError> {
+    let error = |iter: &mut SourceIter<'_>, overflow: bool| {
+        // swallow the rest of something like 123abc so it's one error
+        get_numeric_string(iter, is_identifier_continue);
+        let lexeme = iter.slice_from(start).to_string();
+        let kind = if overflow {
+            ScanErrorKind::NumberTooLarge(lexeme)
+        } else {
+            ScanErrorKind::MalformedNumber(lexeme)
+        };
+        Err(ScanError { kind, span: iter.span_from(start) })
+    };
+
+    // hex and binary
+    if first_char == '0' {
+        let radix = match iter.peek() {
+            Some('x') | Some('X') => 16,
+            Some('b') | Some('B') => 2,
+            _ => 10
+        };
+        if radix != 10 {
+            iter.next();
+            let digits = get_numeric_string(iter, |c| c.is_ascii_alphanumeric());
+            if !has_valid_separators(&digits) || !digits.chars().all(|c| c == '_' || c.is_digit(radix)) {
+                return error(iter, false);
+            }
+            return match u64::from_str_radix(&digits.replace('_', ""), radix) {
+                Ok(value) => Ok(Token::NUMBER(value as f64)),
+                Err(_) => error(iter, true)
+            };
+        }
+    }
+
     let mut numeric_literal = String::new();
     numeric_literal.push(first_char);
+    numeric_literal += &get_numeric_string(iter, is_digit);
+    if !has_valid_separators(&numeric_literal) {
+        return error(iter, false);
+    }
 
-    numeric_literal = numeric_literal + &get_numeric_string(iter);
+    // add fractional part, only if there's a digit after the . so that
+    // 1.method() is still a method call on 1
+    if iter.peek() == Some(&'.') && iter.peek_next().is_some_and(is_digit) {
+        numeric_literal.push(iter.next().expect("We just peeked it"));
 
-    // add fractional part
-    let final_numeric_lit = match iter.peek() {
-        Some(next) => {
-            if *next == '.' { 
-                // TODO: needs one more peek ahead to check if actually digit after .
-                // requires external crate lol, for later
-                numeric_literal.push(iter.next().expect("We just peeked it"));
+        let fraction = get_numeric_string(iter, is_digit);
+        if !has_valid_separators(&fraction) {
+            return error(iter, false);
+        }
+        numeric_literal += &fraction;
+    }
 
-                numeric_literal + &get_numeric_string(iter)
-            }
-            else {
-                numeric_literal
-            }
+    // add exponent
+    if let Some('e') | Some('E') = iter.peek() {
+        numeric_literal.push(iter.next().expect("We just peeked it"));
+        if let Some('+') | Some('-') = iter.peek() {
+            numeric_literal.push(iter.next().expect("We just peeked it"));
         }
-        None => numeric_literal
-    };
 
-    Token::NUMBER(final_numeric_lit.parse().expect("This should be a valid number"))
+        let exponent = get_numeric_string(iter, is_digit);
+        if !has_valid_separators(&exponent) {
+            return error(iter, false);
+        }
+        numeric_literal += &exponent;
+    }
+
+    // stuff like 123abc
+    if iter.peek().is_some_and(|c| is_identifier_continue(*c)) {
+        return error(iter, false);
+    }
+
+    let value: f64 = numeric_literal.replace('_', "").parse().expect("This should be a valid number");
+    if value.is_infinite() {
+        return error(iter, true);
+    }
+    Ok(Token::NUMBER(value))
 }
 
 
-fn get_identifers_or_keywords(iter: &mut std::iter::Peekable<std::str::Chars<'_>>, first_char: char) -> Token {
+fn is_identifier_start(c: char) -> bool {
+    c == '_' || c.is_xid_start()
+}
+
+fn is_identifier_continue(c: char) -> bool {
+    c.is_xid_continue()
+}
+
+fn get_identifers_or_keywords(iter: &mut SourceIter<'_>, first_char: char) -> Token {
     let mut identifier = String::new();
     identifier.push(first_char);
 
     while let Some(next) = iter.peek(){
-        if next.is_ascii_alphanumeric(){
+        if is_identifier_continue(*next){
             identifier.push(iter.next().expect("We just peeked it"));
         }
         else {
@@ -122,46 +768,60 @@ fn get_identifers_or_keywords(iter: &mut std::iter::Peekable<std::str::Chars<'_>
         }
     }
 
-    // TODO: make this not run every fucking time
-    let keyword_map = HashMap::from([
-        ("and",    Token::AND),
-        ("class",  Token::CLASS),
-        ("else",   Token::ELSE),
-        ("false",  Token::FALSE),
-        ("for",    Token::FOR),
-        ("fun",    Token::FUN),
-        ("if",     Token::IF),
-        ("nil",    Token::NIL),
-        ("or",     Token::OR),
-        ("print",  Token::PRINT),
-        ("return", Token::RETURN),
-        ("super",  Token::SUPER),
-        ("this",   Token::THIS),
-        ("true",   Token::TRUE),
-        ("var",    Token::VAR),
-        ("while",  Token::WHILE),
-    ]);
+    // so that é typed as one char or as e + combining accent is the same name
+    let identifier: String = identifier.nfc().collect();
+
+    keyword(&identifier).unwrap_or(Token::IDENTIFIER(identifier))
+}
 
-    keyword_map.get(identifier.as_str()).cloned().unwrap_or(Token::IDENTIFIER(identifier))
+pub fn keyword(identifier: &str) -> Option<Token> {
+    match identifier {
+        "and"      => Some(Token::AND),
+        "break"    => Some(Token::BREAK),
+        "class"    => Some(Token::CLASS),
+        "continue" => Some(Token::CONTINUE),
+        "else"     => Some(Token::ELSE),
+        "false"    => Some(Token::FALSE),
+        "for"      => Some(Token::FOR),
+        "fun"      => Some(Token::FUN),
+        "if"       => Some(Token::IF),
+        "nil"      => Some(Token::NIL),
+        "or"       => Some(Token::OR),
+        "print"    => Some(Token::PRINT),
+        "return"   => Some(Token::RETURN),
+        "super"    => Some(Token::SUPER),
+        "this"     => Some(Token::THIS),
+        "true"     => Some(Token::TRUE),
+        "var"      => Some(Token::VAR),
+        "while"    => Some(Token::WHILE),
+        _ => None
+    }
 }
 
 /**
- * Returns None at end of file
+ * Returns None at end of file. Whitespace and comments are skipped unless
+ * `keep_trivia` is set, in which case they come back as tokens too.
  */
-fn scan_single_token(iter: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Option<Token> {
-    'main_loop: while let Some(current) = iter.next() {
+pub(crate) fn scan_single_token(iter: &mut SourceIter<'_>, keep_trivia: bool) -> Option<Result<SpannedToken, ScanError>> {
+    'main_loop: loop {
+        let start = iter.position();
+        let current = iter.next()?;
         let next_token = match current {
             // ez ones
             '(' => Token::LEFT_PAREN,
             ')' => Token::RIGHT_PAREN,
             '{' => Token::LEFT_BRACE,
             '}' => Token::RIGHT_BRACE,
+            '[' => Token::LEFT_BRACKET,
+            ']' => Token::RIGHT_BRACKET,
             ',' => Token::COMMA,
             '.' => Token::DOT,
             '-' => Token::MINUS,
             '+' => Token::PLUS,
             ';' => Token::SEMICOLON,
             '*' => Token::STAR,
+            ':' => Token::COLON,
+            '?' => Token::QUESTION,
 
             '!' =>
                 look_ahead_1_char(iter, '=', Token::BANG_EQUAL, Token::BANG),
@@ -186,85 +846,758 @@ fn scan_single_token(iter: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Opt
                         }
                         iter.next();
                     }
-                    // lol fuck
-                    continue 'main_loop;
+                    if !keep_trivia {
+                        // lol fuck
+                        continue 'main_loop;
+                    }
+                    Token::COMMENT(iter.slice_from(start).to_string())
+                }
+                else if next_char == '*' {
+                    iter.next();
+                    if skip_block_comment(iter) {
+                        if !keep_trivia {
+                            continue 'main_loop;
+                        }
+                        Token::COMMENT(iter.slice_from(start).to_string())
+                    }
+                    else {
+                        return Some(Err(ScanError {
+                            kind: ScanErrorKind::UnterminatedBlockComment,
+                            span: Span { len: 2, ..start }
+                        }))
+                    }
+                }
+                else {
+                    // not a comment
+                    Token::SLASH
                 }
-
-                // else, not double //
-                Token::SLASH
             }
 
-            ' ' | '\r' | '\t' => continue 'main_loop,
-            '\n' => continue 'main_loop, //TODO: line increment
+            ' ' | '\r' | '\t' => {
+                if !keep_trivia {
+                    continue 'main_loop;
+                }
+                while let Some(' ' | '\r' | '\t') = iter.peek() {
+                    iter.next();
+                }
+                Token::WHITESPACE(iter.slice_from(start).to_string())
+            }
+            '\n' => {
+                if !keep_trivia {
+                    continue 'main_loop;
+                }
+                Token::NEWLINE
+            }
 
-            '"' => get_string_literal_token(iter).expect("unclosed string literal"),
+            '"' => {
+                let scanned = if iter.peek() == Some(&'"') && iter.peek_next() == Some('"') {
+                    get_triple_quoted_string_token(iter, start)
+                }
+                else {
+                    get_string_literal_token(iter, start)
+                };
+                match scanned {
+                    Ok(token) => token,
+                    Err(error) => return Some(Err(error))
+                }
+            },
+            'r' if matches!(iter.peek(), Some('"') | Some('#')) => match get_raw_string_token(iter, start) {
+                Ok(token) => token,
+                Err(error) => return Some(Err(error))
+            },
             other => {
                 if is_digit(other) {
-                    get_numeric_literal(iter, other)
+                    match get_numeric_literal(iter, other, start) {
+                        Ok(token) => token,
+                        Err(error) => return Some(Err(error))
+                    }
                 }
-                else if other.is_ascii_alphanumeric() {
+                else if is_identifier_start(other) {
                     get_identifers_or_keywords(iter, other)
                 }
                 else {
-                    todo!()
+                    return Some(Err(ScanError {
+                        kind: ScanErrorKind::UnexpectedCharacter(other),
+                        span: iter.span_from(start)
+                    }))
                 }
             }
         };
 
-        return Some(next_token)
+        return Some(Ok(SpannedToken { token: next_token, span: iter.span_from(start) }))
+    }
+}
+
+
+/**
+ * Lazily scans a source string, one token at a time. Ends with a single EOF
+ * token, and carries on past lexical errors.
+ */
+pub struct Scanner<'a> {
+    iter: SourceIter<'a>,
+    keep_trivia: bool,
+    done: bool,
+}
+
+impl<'a> Scanner<'a> {
+    pub fn new(source_code: &'a str) -> Self {
+        Scanner { iter: SourceIter::new(source_code), keep_trivia: false, done: false }
+    }
+
+    /**
+     * Also emits WHITESPACE, NEWLINE and COMMENT tokens, so the spans of
+     * everything it returns cover the source end to end. Meant for tools
+     * like formatters and highlighters, the parser doesn't want these.
+     */
+    pub fn lossless(source_code: &'a str) -> Self {
+        Scanner { keep_trivia: true, ..Scanner::new(source_code) }
+    }
+}
+
+impl Iterator for Scanner<'_> {
+    type Item = Result<SpannedToken, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        scan_single_token(&mut self.iter, self.keep_trivia).or_else(|| {
+            self.done = true;
+            Some(Ok(SpannedToken { token: Token::EOF, span: self.iter.position() }))
+        })
+    }
+}
+
+/**
+ * What an unfinished token is in the middle of
+ */
+enum Nesting {
+    String,
+    TripleString,
+    // how many # it has to end with
+    RawString(usize),
+    // inside ${ }, and how many { deep
+    Code(usize),
+    BlockComment,
+}
+
+/**
+ * Just enough of the scanner to spot where an unfinished string or block
+ * comment ends. It's fed the source a line at a time and picks up where it
+ * left off, so `ReaderScanner` only has to scan the token properly once,
+ * when it's all there. Getting it wrong only costs a wasted rescan.
+ */
+#[derive(Default)]
+struct EndFinder {
+    nesting: Vec<Nesting>,
+}
+
+impl EndFinder {
+    /**
+     * Returns true once the token it was started on has ended
+     */
+    fn feed(&mut self, text: &str) -> bool {
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            match self.nesting.last_mut() {
+                Some(Nesting::String) => match c {
+                    '"' => { self.nesting.pop(); }
+                    '$' if rest.starts_with('{') => {
+                        rest = &rest[1..];
+                        self.nesting.push(Nesting::Code(0));
+                    }
+                    // so \" doesn't end it and \${ doesn't start code
+                    '\\' if rest.starts_with(['"', '\\', '$']) => rest = &rest[1..],
+                    _ => {}
+                },
+                Some(Nesting::TripleString) => match c {
+                    '"' if rest.starts_with("\"\"") => {
+                        rest = &rest[2..];
+                        self.nesting.pop();
+                    }
+                    '\\' => {
+                        let escaped = rest.chars().next().map_or(0, char::len_utf8);
+                        rest = &rest[escaped..];
+                    }
+                    _ => {}
+                },
+                Some(Nesting::RawString(hashes)) => {
+                    let hashes = *hashes;
+                    if c == '"' && rest.bytes().take_while(|b| *b == b'#').count() >= hashes {
+                        rest = &rest[hashes..];
+                        self.nesting.pop();
+                    }
+                }
+                Some(Nesting::BlockComment) => match c {
+                    '/' if rest.starts_with('*') => {
+                        rest = &rest[1..];
+                        self.nesting.push(Nesting::BlockComment);
+                    }
+                    '*' if rest.starts_with('/') => {
+                        rest = &rest[1..];
+                        self.nesting.pop();
+                    }
+                    _ => {}
+                },
+                Some(Nesting::Code(depth)) => match c {
+                    '{' => *depth += 1,
+                    '}' if *depth == 0 => { self.nesting.pop(); }
+                    '}' => *depth -= 1,
+                    _ => rest = self.code(c, rest),
+                },
+                None => rest = self.code(c, rest),
+            }
+
+            if self.nesting.is_empty() {
+                return true;
+            }
+        }
+        false
+    }
+
+    /**
+     * Steps over a token in code, noting any string or comment it starts
+     */
+    fn code<'t>(&mut self, c: char, rest: &'t str) -> &'t str {
+        match c {
+            '"' if rest.starts_with("\"\"") => {
+                self.nesting.push(Nesting::TripleString);
+                &rest[2..]
+            }
+            '"' => {
+                self.nesting.push(Nesting::String);
+                rest
+            }
+            '/' if rest.starts_with('/') => &rest[rest.find('\n').unwrap_or(rest.len())..],
+            '/' if rest.starts_with('*') => {
+                self.nesting.push(Nesting::BlockComment);
+                &rest[1..]
+            }
+            'r' if rest.starts_with(['"', '#']) => {
+                let hashes = rest.bytes().take_while(|b| *b == b'#').count();
+                if rest[hashes..].starts_with('"') {
+                    self.nesting.push(Nesting::RawString(hashes));
+                    return &rest[hashes + 1..];
+                }
+                &rest[hashes..]
+            }
+            // so an r in the middle of a name doesn't look like a raw string
+            c if is_identifier_start(c) => rest.trim_start_matches(is_identifier_continue),
+            _ => rest
+        }
+    }
+}
+
+/**
+ * Like `Scanner`, but pulls the source a line at a time from a reader.
+ * A line that ends inside a string or block comment gets more lines
+ * appended until the token is finished, so spans stay the same as if the
+ * whole source had been scanned at once.
+ */
+pub struct ReaderScanner<R: BufRead> {
+    reader: R,
+    pending: VecDeque<Result<SpannedToken, ScanError>>,
+    // lines read but not scanned for good yet
+    buffer: String,
+    // where `buffer` starts in the whole source
+    offset: usize,
+    line: usize,
+    column: usize,
+    // follows the unfinished token at the start of `buffer`, if there is one
+    unfinished: Option<EndFinder>,
+    done: bool,
+}
+
+impl<R: BufRead> ReaderScanner<R> {
+    pub fn new(reader: R) -> Self {
+        ReaderScanner {
+            reader,
+            pending: VecDeque::new(),
+            buffer: String::new(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            unfinished: None,
+            done: false,
+        }
+    }
+
+    fn shift(&self, span: Span) -> Span {
+        Span { offset: span.offset + self.offset, line: span.line + self.line - 1, ..span }
+    }
+
+    /**
+     * `shift`s the tokens inside interpolated strings too
+     */
+    fn shift_token(&self, token: SpannedToken) -> SpannedToken {
+        let span = self.shift(token.span);
+        match token.token {
+            Token::INTERPOLATED(segments) => {
+                let segments = segments.into_iter().map(|segment| match segment {
+                    StringSegment::Code(tokens) => StringSegment::Code(tokens.into_iter().map(|token| self.shift_token(token)).collect()),
+                    text => text,
+                }).collect();
+                SpannedToken { token: Token::INTERPOLATED(segments), span }
+            }
+            token => SpannedToken { token, span },
+        }
+    }
+
+    /**
+     * Reads another line and scans whatever can be finished into `pending`
+     */
+    fn fill(&mut self) {
+        let read_from = self.buffer.len();
+
+        // read_line only stops without a \n at the end of the reader,
+        // and reads nothing at all once it's already there
+        let at_end = match self.reader.read_line(&mut self.buffer) {
+            Ok(0) => true,
+            Ok(_) => !self.buffer.ends_with('\n'),
+            Err(error) => {
+                self.done = true;
+                let start = Span { offset: 0, line: 1, column: self.column, len: 0 };
+                self.pending.push_back(Err(ScanError {
+                    kind: ScanErrorKind::ReadFailed(error.to_string()),
+                    span: self.shift(SourceIter::starting_at(&self.buffer, start).position())
+                }));
+                return;
+            }
+        };
+
+        // no point scanning a string or comment again until it's ended
+        if let Some(unfinished) = &mut self.unfinished {
+            if !unfinished.feed(&self.buffer[read_from..]) && !at_end {
+                return;
+            }
+        }
+
+        let start = Span { offset: 0, line: 1, column: self.column, len: 0 };
+        let scanner = Scanner { iter: SourceIter::starting_at(&self.buffer, start), keep_trivia: false, done: false };
+        let items: Vec<_> = scanner.collect();
+
+        // the unfinished token and anything after it wait for more lines
+        let unfinished = if at_end {
+            None
+        } else {
+            items.iter().find_map(|item| match item {
+                Err(error) if error.is_unfinished() => Some(error.span),
+                _ => None,
+            })
+        };
+
+        for item in items {
+            let item = match item {
+                Ok(token) if unfinished.is_some_and(|span| token.span.offset >= span.offset) => continue,
+                Err(error) if unfinished.is_some_and(|span| error.span.offset >= span.offset) => continue,
+                // only the real end of the source gets an EOF
+                Ok(SpannedToken { token: Token::EOF, .. }) if !at_end => continue,
+                Ok(token) => Ok(self.shift_token(token)),
+                Err(error) => Err(ScanError { span: self.shift(error.span), ..error }),
+            };
+            self.pending.push_back(item);
+        }
+
+        // pick up from the unfinished token next time, or the next line
+        let rest = match unfinished {
+            Some(span) => span,
+            None => Span { offset: self.buffer.len(), line: 1 + self.buffer.matches('\n').count(), column: 1, len: 0 },
+        };
+        self.offset += rest.offset;
+        self.line += rest.line - 1;
+        self.column = rest.column;
+        self.buffer.drain(..rest.offset);
+        self.unfinished = unfinished.map(|_| {
+            let mut finder = EndFinder::default();
+            finder.feed(&self.buffer);
+            finder
+        });
+        self.done = at_end;
     }
+}
 
-    return None;
+impl<R: BufRead> Iterator for ReaderScanner<R> {
+    type Item = Result<SpannedToken, ScanError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Some(item);
+            }
+            if self.done {
+                return None;
+            }
+            self.fill();
+        }
+    }
 }
 
 
-pub fn scan_tokens(source_code: &String) -> Vec<Token> {
+/**
+ * Scans the whole source, carrying on past bad input so that every
+ * lexical error in it gets reported at once
+ */
+pub fn scan_tokens(source_code: &str) -> Result<Vec<SpannedToken>, Vec<ScanError>> {
+    collect_tokens(Scanner::new(source_code))
+}
+
+/**
+ * Drains a `Scanner` or `ReaderScanner`, gathering up all the errors
+ */
+pub fn collect_tokens(scanner: impl Iterator<Item = Result<SpannedToken, ScanError>>) -> Result<Vec<SpannedToken>, Vec<ScanError>> {
     let mut result = Vec::new();
+    let mut errors = Vec::new();
 
-    let mut iter: std::iter::Peekable<std::str::Chars<'_>> = source_code.chars().peekable();
-    
-    while let Some(c) = scan_single_token(&mut iter) {
-        result.push(c);
+    for c in scanner {
+        match c {
+            Ok(token) => result.push(token),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
     }
 
-    result.push(Token::EOF);
-    return result;
+    Ok(result)
 }
 
 
 
 #[cfg(test)]
 mod tests {
-    use crate::scanner::Token;
+    use crate::scanner::{ScanError, ScanErrorKind, Span, SpannedToken, StringSegment, Token};
     
     use crate::scanner;
 
+    fn token_kinds(source_code: &str) -> Vec<Token> {
+        scanner::scan_tokens(source_code).unwrap().into_iter().map(|t| t.token).collect()
+    }
+
     #[test]
     fn scan_tokens_tests() {
-        assert_eq!(scanner::scan_tokens(& String::from("+")), vec![Token::PLUS, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("")), vec![Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("     ")), vec![Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("!=")), vec![Token::BANG_EQUAL, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("!!=")), vec![Token::BANG, Token::BANG_EQUAL, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("/")), vec![Token::SLASH, Token::EOF]);
-
-        assert_eq!(scanner::scan_tokens(& String::from("//")), vec![Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("//+")), vec![Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("//\n")), vec![Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("//\n+")), vec![Token::PLUS, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("//asd\n+")), vec![Token::PLUS, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("//asdsad asd \n+")), vec![Token::PLUS, Token::EOF]);
-
-        assert_eq!(scanner::scan_tokens(& String::from(r#""blah""#)), vec![Token::STRING(String::from("blah")), Token::EOF]);
-
-        assert_eq!(scanner::scan_tokens(& String::from("123")), vec![Token::NUMBER("123".parse().unwrap()), Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("1.1")), vec![Token::NUMBER("1.1".parse().unwrap()), Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("1.1 2.2")), vec![Token::NUMBER("1.1".parse().unwrap()), 
+        assert_eq!(token_kinds(& String::from("+")), vec![Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds(""), vec![Token::EOF]);
+        assert_eq!(token_kinds(& String::from("     ")), vec![Token::EOF]);
+        assert_eq!(token_kinds(& String::from("!=")), vec![Token::BANG_EQUAL, Token::EOF]);
+        assert_eq!(token_kinds(& String::from("!!=")), vec![Token::BANG, Token::BANG_EQUAL, Token::EOF]);
+        assert_eq!(token_kinds(& String::from("/")), vec![Token::SLASH, Token::EOF]);
+
+        assert_eq!(token_kinds(& String::from("//")), vec![Token::EOF]);
+        assert_eq!(token_kinds(& String::from("//+")), vec![Token::EOF]);
+        assert_eq!(token_kinds(& String::from("//\n")), vec![Token::EOF]);
+        assert_eq!(token_kinds(& String::from("//\n+")), vec![Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds(& String::from("//asd\n+")), vec![Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds(& String::from("//asdsad asd \n+")), vec![Token::PLUS, Token::EOF]);
+
+        assert_eq!(token_kinds(& String::from(r#""blah""#)), vec![Token::STRING(String::from("blah")), Token::EOF]);
+
+        assert_eq!(token_kinds(& String::from("123")), vec![Token::NUMBER("123".parse().unwrap()), Token::EOF]);
+        assert_eq!(token_kinds(& String::from("1.1")), vec![Token::NUMBER("1.1".parse().unwrap()), Token::EOF]);
+        assert_eq!(token_kinds(& String::from("1.1 2.2")), vec![Token::NUMBER("1.1".parse().unwrap()), 
         Token::NUMBER("2.2".parse().unwrap()), Token::EOF]);
 
-        assert_eq!(scanner::scan_tokens(& String::from("fuck")), vec![Token::IDENTIFIER(String::from("fuck")), Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("and")), vec![Token::AND, Token::EOF]);
-        assert_eq!(scanner::scan_tokens(& String::from("and fuck")), vec![Token::AND, 
+        assert_eq!(token_kinds(& String::from("fuck")), vec![Token::IDENTIFIER(String::from("fuck")), Token::EOF]);
+        assert_eq!(token_kinds(& String::from("and")), vec![Token::AND, Token::EOF]);
+        assert_eq!(token_kinds(& String::from("and fuck")), vec![Token::AND, 
         Token::IDENTIFIER(String::from("fuck")), Token::EOF]);
     }
+
+    #[test]
+    fn scan_tokens_span_tests() {
+        let spans: Vec<Span> = scanner::scan_tokens("var x\n  = \"a\nb\";").unwrap().into_iter().map(|t| t.span).collect();
+        assert_eq!(spans, vec![
+            Span { offset: 0, line: 1, column: 1, len: 3 },
+            Span { offset: 4, line: 1, column: 5, len: 1 },
+            Span { offset: 8, line: 2, column: 3, len: 1 },
+            Span { offset: 10, line: 2, column: 5, len: 5 },
+            Span { offset: 15, line: 3, column: 3, len: 1 },
+            Span { offset: 16, line: 3, column: 4, len: 0 },
+        ]);
+
+        // columns count chars, offsets count bytes
+        let spans: Vec<Span> = scanner::scan_tokens("\"é\" +").unwrap().into_iter().map(|t| t.span).collect();
+        assert_eq!(spans[1], Span { offset: 5, line: 1, column: 5, len: 1 });
+    }
+
+    #[test]
+    fn scan_tokens_error_tests() {
+        assert_eq!(scanner::scan_tokens("1 @ 2 #"), Err(vec![
+            ScanError { kind: ScanErrorKind::UnexpectedCharacter('@'),
+                        span: Span { offset: 2, line: 1, column: 3, len: 1 } },
+            ScanError { kind: ScanErrorKind::UnexpectedCharacter('#'),
+                        span: Span { offset: 6, line: 1, column: 7, len: 1 } },
+        ]));
+
+        assert_eq!(scanner::scan_tokens("+\n\"abc"), Err(vec![
+            ScanError { kind: ScanErrorKind::UnterminatedString,
+                        span: Span { offset: 2, line: 2, column: 1, len: 4 } },
+        ]));
+
+        assert_eq!(scanner::scan_tokens("@").unwrap_err()[0].to_string(),
+                   "[line 1, column 1] Error: Unexpected character '@'.");
+    }
+
+    #[test]
+    fn scan_string_escape_tests() {
+        assert_eq!(token_kinds(r#""a\nb\t\r\\\"\0""#), vec![Token::STRING(String::from("a\nb\t\r\\\"\0")), Token::EOF]);
+        assert_eq!(token_kinds(r#""\u{41}\u{1F600}""#), vec![Token::STRING(String::from("A😀")), Token::EOF]);
+
+        assert_eq!(scanner::scan_tokens(r#"+ "ab\qc" +"#), Err(vec![
+            ScanError { kind: ScanErrorKind::InvalidEscape(String::from(r"\q")),
+                        span: Span { offset: 5, line: 1, column: 6, len: 2 } },
+        ]));
+        assert_eq!(scanner::scan_tokens(r#""\u{D800}""#).unwrap_err()[0].kind,
+                   ScanErrorKind::InvalidEscape(String::from(r"\u{D800}")));
+        assert_eq!(scanner::scan_tokens(r#""\u{1234567}""#).unwrap_err()[0].kind,
+                   ScanErrorKind::InvalidEscape(String::from(r"\u{1234567}")));
+        assert_eq!(scanner::scan_tokens(r#""\u41""#).unwrap_err()[0].kind,
+                   ScanErrorKind::InvalidEscape(String::from(r"\u")));
+        // escaped quote doesn't close the string
+        assert_eq!(scanner::scan_tokens(r#""\""#).unwrap_err()[0].kind,
+                   ScanErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn scan_block_comment_tests() {
+        assert_eq!(token_kinds("/**/"), vec![Token::EOF]);
+        assert_eq!(token_kinds("+/* - */+"), vec![Token::PLUS, Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds("/* /* - */ - */+"), vec![Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds("/* // */+"), vec![Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds("/***/ / *"), vec![Token::SLASH, Token::STAR, Token::EOF]);
+
+        // newlines inside still count
+        let spans: Vec<Span> = scanner::scan_tokens("/*\n\n*/ +").unwrap().into_iter().map(|t| t.span).collect();
+        assert_eq!(spans[0], Span { offset: 7, line: 3, column: 4, len: 1 });
+
+        assert_eq!(scanner::scan_tokens("+\n /* /* */"), Err(vec![
+            ScanError { kind: ScanErrorKind::UnterminatedBlockComment,
+                        span: Span { offset: 3, line: 2, column: 2, len: 2 } },
+        ]));
+    }
+
+    #[test]
+    fn scan_numeric_literal_tests() {
+        assert_eq!(token_kinds("0x1F 0XfF 0b1010 0B1"), vec![Token::NUMBER(31.0), Token::NUMBER(255.0),
+            Token::NUMBER(10.0), Token::NUMBER(1.0), Token::EOF]);
+        assert_eq!(token_kinds("1e9 2.5E-3 1e+2"), vec![Token::NUMBER(1e9), Token::NUMBER(2.5e-3),
+            Token::NUMBER(100.0), Token::EOF]);
+        assert_eq!(token_kinds("1_000_000 0xFF_FF 1_0.0_1"), vec![Token::NUMBER(1_000_000.0),
+            Token::NUMBER(65535.0), Token::NUMBER(10.01), Token::EOF]);
+
+        // trailing dot isn't part of the number
+        assert_eq!(token_kinds("1."), vec![Token::NUMBER(1.0), Token::DOT, Token::EOF]);
+        assert_eq!(token_kinds("1.foo"), vec![Token::NUMBER(1.0), Token::DOT,
+            Token::IDENTIFIER(String::from("foo")), Token::EOF]);
+
+        let error_kind = |source| scanner::scan_tokens(source).unwrap_err()[0].kind.clone();
+        assert_eq!(error_kind("0x"), ScanErrorKind::MalformedNumber(String::from("0x")));
+        assert_eq!(error_kind("0b102"), ScanErrorKind::MalformedNumber(String::from("0b102")));
+        assert_eq!(error_kind("0x_1"), ScanErrorKind::MalformedNumber(String::from("0x_1")));
+        assert_eq!(error_kind("1__0"), ScanErrorKind::MalformedNumber(String::from("1__0")));
+        assert_eq!(error_kind("1_ + 2"), ScanErrorKind::MalformedNumber(String::from("1_")));
+        assert_eq!(error_kind("1e"), ScanErrorKind::MalformedNumber(String::from("1e")));
+        assert_eq!(error_kind("1e+"), ScanErrorKind::MalformedNumber(String::from("1e+")));
+        assert_eq!(error_kind("123abc"), ScanErrorKind::MalformedNumber(String::from("123abc")));
+        assert_eq!(error_kind("1e999"), ScanErrorKind::NumberTooLarge(String::from("1e999")));
+        assert_eq!(error_kind("0x1_0000_0000_0000_0000"),
+            ScanErrorKind::NumberTooLarge(String::from("0x1_0000_0000_0000_0000")));
+
+        assert_eq!(scanner::scan_tokens("+ 0b2").unwrap_err()[0].span, Span { offset: 2, line: 1, column: 3, len: 3 });
+    }
+
+    #[test]
+    fn scan_identifier_tests() {
+        assert_eq!(token_kinds("my_var _private __ a1"), vec![Token::IDENTIFIER(String::from("my_var")),
+            Token::IDENTIFIER(String::from("_private")), Token::IDENTIFIER(String::from("__")),
+            Token::IDENTIFIER(String::from("a1")), Token::EOF]);
+        assert_eq!(token_kinds("größe π 変数"), vec![Token::IDENTIFIER(String::from("größe")),
+            Token::IDENTIFIER(String::from("π")), Token::IDENTIFIER(String::from("変数")), Token::EOF]);
+
+        // precomposed and decomposed é are the same name
+        assert_eq!(token_kinds("caf\u{e9}"), token_kinds("cafe\u{301}"));
+        assert_eq!(token_kinds("cafe\u{301}"), vec![Token::IDENTIFIER(String::from("caf\u{e9}")), Token::EOF]);
+
+        // not XID_Start
+        assert_eq!(scanner::scan_tokens("\u{301}").unwrap_err()[0].kind, ScanErrorKind::UnexpectedCharacter('\u{301}'));
+        assert_eq!(scanner::scan_tokens("a😀").unwrap_err()[0].kind, ScanErrorKind::UnexpectedCharacter('😀'));
+    }
+
+    #[test]
+    fn scanner_iterator_tests() {
+        let mut scanner = scanner::Scanner::new("1 @ +");
+        assert_eq!(scanner.next().map(|t| t.unwrap().token), Some(Token::NUMBER(1.0)));
+        assert_eq!(scanner.next().map(|t| t.unwrap_err().kind), Some(ScanErrorKind::UnexpectedCharacter('@')));
+        assert_eq!(scanner.next().map(|t| t.unwrap().token), Some(Token::PLUS));
+        assert_eq!(scanner.next().map(|t| t.unwrap().token), Some(Token::EOF));
+        assert_eq!(scanner.next(), None);
+        assert_eq!(scanner.next(), None);
+    }
+
+    #[test]
+    fn reader_scanner_tests() {
+        let sources = [
+            "var x = 1;\nprint x;\n",
+            "print \"multi\nline\" + 1;\n/* a\n/* b */\n*/ 2 3",
+            "1 @\n\"unterminated\n+ 2",
+            "print \"abc\n",
+            "1 /* x\n",
+            "a \"b\nc\nd\" e \"f\ng\" h\n",
+            "a /* b *\n/ c\n/* d\n/* e */\nf */ g\n",
+            "x = \"\"\"\n    one\n    two\n    \"\"\" + \"${y\n}\";\n",
+            "a\r\n\"${@}\" /* b\r\n*/ \"${c\r\n}\"\r\n",
+            "r#\"a \"\n\"# b\n\"\"\"\n \" \"\"\n\"\"\" /* c /*\n*/ d\n*/ e\n",
+            "\"a ${ \"b\n}\" + \"c\" } d\" \\\"\n\" f r\"g\n\" h\n",
+            "",
+        ];
+
+        // same tokens, spans and errors as scanning it all at once
+        for source in sources {
+            let expected: Vec<_> = scanner::Scanner::new(source).collect();
+            let streamed: Vec<_> = scanner::ReaderScanner::new(source.as_bytes()).collect();
+            assert_eq!(streamed, expected);
+        }
+    }
+
+    #[test]
+    fn end_finder_tests() {
+        // true only on the line the token ends on
+        let ends = |lines: &[&str]| -> Vec<bool> {
+            let mut finder = scanner::EndFinder::default();
+            lines.iter().map(|line| finder.feed(line)).collect()
+        };
+        assert_eq!(ends(&["/* a /* b\n", "*/\n", "*/ x\n"]), vec![false, false, true]);
+        assert_eq!(ends(&["\"a \\\" ${ \"}\"\n", "} b\"\n"]), vec![false, true]);
+        assert_eq!(ends(&["r##\"a\"#\n", "\"##\n"]), vec![false, true]);
+        assert_eq!(ends(&["\"\"\"\n", "a \" \"\"\n", "\"\"\"\n"]), vec![false, false, true]);
+        assert_eq!(ends(&["\"${ // }\"\n", "}\"\n"]), vec![false, true]);
+    }
+
+    #[test]
+    fn lossless_scanner_tests() {
+        let tokens: Vec<Token> = scanner::Scanner::lossless("var x = 1; // hi\n\t/* a /* b */ */print x;")
+            .map(|t| t.unwrap().token).collect();
+        assert_eq!(tokens, vec![
+            Token::VAR, Token::WHITESPACE(String::from(" ")), Token::IDENTIFIER(String::from("x")),
+            Token::WHITESPACE(String::from(" ")), Token::EQUAL, Token::WHITESPACE(String::from(" ")),
+            Token::NUMBER(1.0), Token::SEMICOLON, Token::WHITESPACE(String::from(" ")),
+            Token::COMMENT(String::from("// hi")), Token::NEWLINE, Token::WHITESPACE(String::from("\t")),
+            Token::COMMENT(String::from("/* a /* b */ */")), Token::PRINT, Token::WHITESPACE(String::from(" ")),
+            Token::IDENTIFIER(String::from("x")), Token::SEMICOLON, Token::EOF,
+        ]);
+
+        let source = "fun f(a) {\r\n  return a * 0x1F; /* x\n y */\n}\n\n  print \"s\\n\" + f(1.5e3);  // end";
+        let rebuilt: String = scanner::Scanner::lossless(source)
+            .map(|t| { let span = t.unwrap().span; &source[span.offset..span.offset + span.len] })
+            .collect();
+        assert_eq!(rebuilt, source);
+
+        // normal mode still skips all of it
+        assert_eq!(token_kinds(" \t// c\n/* c */\n"), vec![Token::EOF]);
+    }
+
+    #[test]
+    fn scan_interpolation_tests() {
+        let code = |tokens: Vec<Token>| StringSegment::Code(tokens.into_iter()
+            .map(|token| SpannedToken { token, span: Span::default() }).collect());
+        fn without_spans(token: Token) -> Token {
+            match token {
+                Token::INTERPOLATED(segments) => Token::INTERPOLATED(segments.into_iter().map(|segment| match segment {
+                    StringSegment::Code(tokens) => StringSegment::Code(tokens.into_iter()
+                        .map(|t| SpannedToken { token: without_spans(t.token), span: Span::default() }).collect()),
+                    text => text
+                }).collect()),
+                other => other
+            }
+        }
+        let interpolated = |source| without_spans(token_kinds(source).remove(0));
+
+        assert_eq!(interpolated(r#""x = ${x + 1}!""#), Token::INTERPOLATED(vec![
+            StringSegment::Text(String::from("x = ")),
+            code(vec![Token::IDENTIFIER(String::from("x")), Token::PLUS, Token::NUMBER(1.0), Token::EOF]),
+            StringSegment::Text(String::from("!")),
+        ]));
+        assert_eq!(interpolated(r#""${a}${"${b}"}""#), Token::INTERPOLATED(vec![
+            code(vec![Token::IDENTIFIER(String::from("a")), Token::EOF]),
+            code(vec![Token::INTERPOLATED(vec![code(vec![Token::IDENTIFIER(String::from("b")), Token::EOF])]), Token::EOF]),
+        ]));
+        // braces inside the expression have to match
+        assert_eq!(interpolated(r#""${ {} }""#), Token::INTERPOLATED(vec![
+            code(vec![Token::LEFT_BRACE, Token::RIGHT_BRACE, Token::EOF]),
+        ]));
+
+        // not interpolation
+        assert_eq!(token_kinds(r#""$x \${x} {}""#), vec![Token::STRING(String::from("$x ${x} {}")), Token::EOF]);
+
+        // inner tokens keep their real position
+        let Token::INTERPOLATED(segments) = token_kinds("\n \"a${b}\"").remove(0) else { panic!("Expected interpolation") };
+        let StringSegment::Code(tokens) = &segments[1] else { panic!("Expected code") };
+        assert_eq!(tokens[0].span, Span { offset: 6, line: 2, column: 6, len: 1 });
+
+        assert_eq!(scanner::scan_tokens(r#""${1 @}" + 2"#).unwrap_err(), vec![
+            ScanError { kind: ScanErrorKind::UnexpectedCharacter('@'),
+                        span: Span { offset: 5, line: 1, column: 6, len: 1 } },
+        ]);
+        assert_eq!(scanner::scan_tokens(r#""${x"#).unwrap_err()[0].kind, ScanErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn scan_raw_string_tests() {
+        assert_eq!(token_kinds(r#"r"C:\dir\${x}""#), vec![Token::STRING(String::from(r"C:\dir\${x}")), Token::EOF]);
+        assert_eq!(token_kinds(r###"r#"say "hi""#"###), vec![Token::STRING(String::from(r#"say "hi""#)), Token::EOF]);
+        assert_eq!(token_kinds(r###"r##"a "# b"##"###), vec![Token::STRING(String::from(r##"a "# b"##)), Token::EOF]);
+        assert_eq!(token_kinds("r\"a\nb\" +"), vec![Token::STRING(String::from("a\nb")), Token::PLUS, Token::EOF]);
+        assert_eq!(token_kinds("r rx"), vec![Token::IDENTIFIER(String::from("r")),
+            Token::IDENTIFIER(String::from("rx")), Token::EOF]);
+
+        let spans: Vec<Span> = scanner::scan_tokens("r\"\n\n\" +").unwrap().into_iter().map(|t| t.span).collect();
+        assert_eq!(spans[1], Span { offset: 6, line: 3, column: 3, len: 1 });
+
+        assert_eq!(scanner::scan_tokens(r###"r#"a""###).unwrap_err()[0].kind, ScanErrorKind::UnterminatedString);
+        assert_eq!(scanner::scan_tokens("r#x").unwrap_err()[0].kind, ScanErrorKind::MalformedRawString);
+    }
+
+    #[test]
+    fn scan_triple_quoted_string_tests() {
+        let source = "\"\"\"\n    SELECT *\n      FROM t\n\n    WHERE x = \"y\"\n    \"\"\"";
+        assert_eq!(token_kinds(source), vec![
+            Token::STRING(String::from("SELECT *\n  FROM t\n\nWHERE x = \"y\"")), Token::EOF]);
+
+        assert_eq!(token_kinds("\"\"\"one line\"\"\""), vec![Token::STRING(String::from("one line")), Token::EOF]);
+        assert_eq!(token_kinds("\"\"\"\"\"\""), vec![Token::STRING(String::new()), Token::EOF]);
+        assert_eq!(token_kinds("\"\"\"first\n    second\n  \"\"\""), vec![
+            Token::STRING(String::from("first\nsecond")), Token::EOF]);
+        // escapes still work, but not interpolation
+        assert_eq!(token_kinds("\"\"\"\n  a\\tb \\\"\"\" ${x}\n  \"\"\""), vec![
+            Token::STRING(String::from("a\tb \"\"\" ${x}")), Token::EOF]);
+        assert_eq!(token_kinds("\"\"\"\r\n    a\r\n    b\r\n    \"\"\""), vec![
+            Token::STRING(String::from("a\nb")), Token::EOF]);
+        assert_eq!(token_kinds("\"\" \"x\""), vec![Token::STRING(String::new()), Token::STRING(String::from("x")), Token::EOF]);
+
+        let spans: Vec<Span> = scanner::scan_tokens("\"\"\"\na\n\"\"\" +").unwrap().into_iter().map(|t| t.span).collect();
+        assert_eq!(spans[1], Span { offset: 10, line: 3, column: 5, len: 1 });
+
+        assert_eq!(scanner::scan_tokens("\"\"\"\n  ok\n  bad \\q\n  \"\"\"").unwrap_err(), vec![
+            ScanError { kind: ScanErrorKind::InvalidEscape(String::from(r"\q")),
+                        span: Span { offset: 15, line: 3, column: 7, len: 2 } },
+        ]);
+        assert_eq!(scanner::scan_tokens("\"\"\"\nabc\"\"").unwrap_err()[0].kind, ScanErrorKind::UnterminatedString);
+    }
+
+    #[test]
+    fn token_display_tests() {
+        assert_eq!(Token::PLUS.to_string(), "+");
+        assert_eq!(Token::LESS_EQUAL.to_string(), "<=");
+        assert_eq!(Token::WHILE.to_string(), "while");
+        assert_eq!(Token::CONTINUE.to_string(), "continue");
+        assert_eq!(Token::COLON.to_string(), ":");
+        assert_eq!(Token::QUESTION.to_string(), "?");
+        assert_eq!(Token::LEFT_BRACKET.to_string(), "[");
+        assert_eq!(Token::IDENTIFIER(String::from("x")).to_string(), "x");
+        assert_eq!(Token::STRING(String::from("a\"b")).to_string(), "\"a\\\"b\"");
+        assert_eq!(Token::NUMBER(1.5).to_string(), "1.5");
+        assert_eq!(Token::EOF.to_string(), "end");
+    }
 }
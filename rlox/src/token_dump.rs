@@ -0,0 +1,156 @@
+use std::fmt::Write;
+
+use crate::scanner::{ScanError, Scanner, SpannedToken, Token};
+
+pub enum DumpFormat {
+    Text,
+    Json,
+}
+
+pub struct TokenDump {
+    pub output: String,
+    pub errors: Vec<ScanError>,
+}
+
+/**
+ * Value a token carries, as it would be written in JSON
+ */
+fn literal_json(token: &Token) -> String {
+    match token {
+        Token::IDENTIFIER(str) | Token::STRING(str) => json_string(str),
+        Token::NUMBER(x) if x.is_finite() => x.to_string(),
+        _ => String::from("null")
+    }
+}
+
+fn json_string(str: &str) -> String {
+    let mut result = String::from("\"");
+    for c in str.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(result, "\\u{:04x}", c as u32).expect("Writing to a String"),
+            c => result.push(c)
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn json_array(items: &[String]) -> String {
+    if items.is_empty() {
+        return String::from("[]");
+    }
+    format!("[\n{}\n  ]", items.join(",\n"))
+}
+
+fn lexeme<'a>(source: &'a str, token: &SpannedToken) -> &'a str {
+    &source[token.span.offset..token.span.offset + token.span.len]
+}
+
+fn text_line(source: &str, token: &SpannedToken) -> String {
+    let position = format!("{}:{}", token.span.line, token.span.column);
+    let literal = match &token.token {
+        Token::IDENTIFIER(str) | Token::STRING(str) => format!("{:?}", str),
+        Token::NUMBER(x) => x.to_string(),
+        _ => String::new()
+    };
+    // keep multi-line strings on one line
+    let lexeme = lexeme(source, token).replace('\n', "\\n").replace('\r', "\\r").replace('\t', "\\t");
+    format!("{:<8} {:<14} {:<20} {}", position, token.token.kind_name(), lexeme, literal)
+        .trim_end()
+        .to_string()
+}
+
+fn json_object(source: &str, token: &SpannedToken) -> String {
+    format!(r#"{{"kind": {}, "lexeme": {}, "literal": {}, "offset": {}, "line": {}, "column": {}, "length": {}}}"#,
+        json_string(token.token.kind_name()), json_string(lexeme(source, token)), literal_json(&token.token),
+        token.span.offset, token.span.line, token.span.column, token.span.len)
+}
+
+/**
+ * Lists every token in the source, one per line for Text, or as a JSON
+ * object with "tokens" and "errors" arrays for Json. Tokens on either side
+ * of a lexical error still get listed.
+ */
+pub fn dump_tokens(source: &str, format: DumpFormat) -> TokenDump {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    for scanned in Scanner::new(source) {
+        match scanned {
+            Ok(token) => tokens.push(token),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    let output = match format {
+        DumpFormat::Text => tokens.iter()
+            .map(|token| text_line(source, token) + "\n")
+            .collect(),
+        DumpFormat::Json => {
+            let token_objects: Vec<String> = tokens.iter()
+                .map(|token| format!("    {}", json_object(source, token)))
+                .collect();
+            let error_objects: Vec<String> = errors.iter()
+                .map(|error| format!(r#"    {{"message": {}, "offset": {}, "line": {}, "column": {}, "length": {}}}"#,
+                    json_string(&error.to_string()), error.span.offset, error.span.line, error.span.column, error.span.len))
+                .collect();
+            format!("{{\n  \"tokens\": {},\n  \"errors\": {}\n}}\n",
+                json_array(&token_objects), json_array(&error_objects))
+        }
+    };
+
+    TokenDump { output, errors }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::token_dump::{dump_tokens, DumpFormat};
+
+    #[test]
+    fn dump_text_tests() {
+        let dump = dump_tokens("var x = \"hi\";\nprint 1.5;\nr\"a\nb\"", DumpFormat::Text);
+        assert_eq!(dump.output, "\
+1:1      VAR            var
+1:5      IDENTIFIER     x                    \"x\"
+1:7      EQUAL          =
+1:9      STRING         \"hi\"                 \"hi\"
+1:13     SEMICOLON      ;
+2:1      PRINT          print
+2:7      NUMBER         1.5                  1.5
+2:10     SEMICOLON      ;
+3:1      STRING         r\"a\\nb\"              \"a\\nb\"
+4:3      EOF
+");
+        assert!(dump.errors.is_empty());
+
+        assert_eq!(dump_tokens("", DumpFormat::Json).output, r#"{
+  "tokens": [
+    {"kind": "EOF", "lexeme": "", "literal": null, "offset": 0, "line": 1, "column": 1, "length": 0}
+  ],
+  "errors": []
+}
+"#);
+    }
+
+    #[test]
+    fn dump_json_tests() {
+        let dump = dump_tokens("\"a\\\"b\" @ 2", DumpFormat::Json);
+        assert_eq!(dump.output, r#"{
+  "tokens": [
+    {"kind": "STRING", "lexeme": "\"a\\\"b\"", "literal": "a\"b", "offset": 0, "line": 1, "column": 1, "length": 6},
+    {"kind": "NUMBER", "lexeme": "2", "literal": 2, "offset": 9, "line": 1, "column": 10, "length": 1},
+    {"kind": "EOF", "lexeme": "", "literal": null, "offset": 10, "line": 1, "column": 11, "length": 0}
+  ],
+  "errors": [
+    {"message": "[line 1, column 8] Error: Unexpected character '@'.", "offset": 7, "line": 1, "column": 8, "length": 1}
+  ]
+}
+"#);
+        assert_eq!(dump.errors.len(), 1);
+    }
+}